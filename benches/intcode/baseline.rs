@@ -1,70 +1,12 @@
-use crate::io::{self, stdin, BufRead};
-use std::collections::VecDeque;
-use std::fmt::{self, Debug, Formatter};
-use std::num::ParseIntError;
-use std::str::FromStr;
-use std::vec::Vec;
-
-use crate::numbers::DigitIterable;
-
-pub type Int = i64;
-
-#[derive(Debug, Clone)]
-pub struct Program {
-    instructions: Vec<Int>,
-}
-
-#[derive(Debug)]
-pub enum ParseProgramError {
-    NotInteger(String, ParseIntError),
-    IOError(io::Error),
-}
-
-impl Program {
-    pub fn from_stdin() -> Result<Program, ParseProgramError> {
-        let stdin = stdin();
-        let mut line = String::new();
-        stdin
-            .lock()
-            .read_line(&mut line)
-            .map_err(|e| ParseProgramError::IOError(e))?;
-        line.parse::<Program>()
-    }
+// The interpreter as it was before the decode cache, compiled code and the
+// rest, kept so the benchmarks can show what they cost or save. It's left as it
+// was, lints and all.
+#![allow(dead_code, clippy::all)]
 
-    pub fn into_fn(self) -> impl Fn(Vec<Int>) -> Vec<Int> {
-        move |input| {
-            let mut vm = VM::of(&self);
-            for i in input {
-                vm.input(i);
-            }
-            let mut ret = Vec::new();
-            loop {
-                match vm.next_state() {
-                    Ok(State::Outputting(i)) => ret.push(i),
-                    Ok(State::Finished) => return ret,
-                    Ok(s) => panic!("Unexpected state: {:?}", s),
-                    Err(e) => panic!("{:?}", e),
-                }
-            }
-        }
-    }
-}
-
-impl FromStr for Program {
-    type Err = ParseProgramError;
+use std::collections::VecDeque;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let instructions = s
-            .trim()
-            .split(',')
-            .map(|s| {
-                s.parse::<Int>()
-                    .map_err(|e| ParseProgramError::NotInteger(s.to_owned(), e))
-            })
-            .collect::<Result<Vec<Int>, Self::Err>>()?;
-        Ok(Program { instructions })
-    }
-}
+use aoc::intcode::Int;
+use aoc::numbers::DigitIterable;
 
 #[derive(Clone, Copy, Debug)]
 pub enum State {
@@ -83,6 +25,7 @@ pub enum Error {
     UnsupportedSet(Mode),
 }
 
+#[derive(Debug)]
 pub struct ExecError {
     mem: Vec<Int>,
     insn: usize,
@@ -154,9 +97,9 @@ pub struct VM {
 }
 
 impl VM {
-    pub fn of(program: &Program) -> Self {
+    pub fn of(words: &[Int]) -> Self {
         VM {
-            mem: program.instructions.clone(),
+            mem: words.to_vec(),
             insn: 0,
             inbuf: VecDeque::new(),
             relbase: 0,
@@ -176,23 +119,10 @@ impl VM {
         }
     }
 
-    pub fn is_finished(&self) -> bool {
-        self.peek()
-            .map(|o| Insn::of((o % 100) as u8).unwrap_or(Insn::End))
-            .unwrap_or(Insn::End)
-            == Insn::End
-    }
-
     pub fn input(&mut self, input: Int) {
         self.inbuf.push_back(input);
     }
 
-    pub fn input_ascii(&mut self, input: &str) {
-        for c in input.chars() {
-            self.input(c as Int);
-        }
-    }
-
     fn peek(&self) -> VMResult<Int> {
         self.mem
             .get(self.insn)
@@ -336,46 +266,3 @@ impl VM {
         Ok(None)
     }
 }
-
-fn format_mem(f: &mut Formatter, mem: &Vec<Int>, insn: usize) -> fmt::Result {
-    mem.iter()
-        .take(insn)
-        .map(|v| write!(f, "{},", v))
-        .collect::<fmt::Result>()?;
-    let mut iter = mem.iter().skip(insn);
-    write!(f, "[")?;
-    iter.next()
-        .map(|val| write!(f, "{}],", val))
-        .unwrap_or(Ok(()))?;
-    iter.map(|v| write!(f, "{},", v)).collect::<fmt::Result>()?;
-    write!(f, "END")?;
-    Ok(())
-}
-
-impl Debug for VM {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        format_mem(f, &self.mem, self.insn)
-    }
-}
-
-impl Debug for ExecError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}\n", self.error)?;
-        format_mem(f, &self.mem, self.insn)
-    }
-}
-
-impl Iterator for VM {
-    type Item = Int;
-
-    fn next(&mut self) -> Option<Int> {
-        match self.next_state() {
-            Ok(s) => match s {
-                State::AwaitingInput => None,
-                State::Outputting(i) => Some(i),
-                State::Finished => None,
-            },
-            Err(_) => None,
-        }
-    }
-}
@@ -0,0 +1,177 @@
+use std::time::{Duration, Instant};
+
+use aoc::intcode::{Int, Program, State, VM};
+
+mod baseline;
+
+fn load(day: u32) -> Program {
+    Program::from_file(format!("input/{}.txt", day)).unwrap()
+}
+
+fn bench<F: FnMut() -> Int>(name: &str, mut f: F) {
+    let mut checksum = f();
+    let mut iters = 0;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) {
+        checksum = checksum.wrapping_add(f());
+        iters += 1;
+    }
+    let per_iter = start.elapsed() / iters;
+    println!(
+        "{:<32} {:>12?} / iter ({})",
+        name,
+        per_iter,
+        checksum % 1000
+    );
+}
+
+// Just enough of a VM for the benchmarks, so they run the baseline the same way.
+trait Machine: Clone {
+    fn get(&self, addr: usize) -> Int;
+    fn set(&mut self, addr: usize, val: Int);
+    fn input(&mut self, input: Int);
+    // The next output, or None once the program has finished.
+    fn output(&mut self) -> Option<Int>;
+    // Whether the program gets to its first stop without an error.
+    fn runs(&mut self) -> bool;
+}
+
+impl Machine for VM {
+    fn get(&self, addr: usize) -> Int {
+        self.mem[addr]
+    }
+
+    fn set(&mut self, addr: usize, val: Int) {
+        self.mem[addr] = val;
+    }
+
+    fn input(&mut self, input: Int) {
+        VM::input(self, input);
+    }
+
+    fn output(&mut self) -> Option<Int> {
+        match self.next_state().unwrap() {
+            State::Outputting(i) => Some(i),
+            State::Finished => None,
+            s => panic!("Unexpected state: {:?}", s),
+        }
+    }
+
+    fn runs(&mut self) -> bool {
+        self.next_state().is_ok()
+    }
+}
+
+impl Machine for baseline::VM {
+    fn get(&self, addr: usize) -> Int {
+        self.mem[addr]
+    }
+
+    fn set(&mut self, addr: usize, val: Int) {
+        self.mem[addr] = val;
+    }
+
+    fn input(&mut self, input: Int) {
+        baseline::VM::input(self, input);
+    }
+
+    fn output(&mut self) -> Option<Int> {
+        match self.next_state().unwrap() {
+            baseline::State::Outputting(i) => Some(i),
+            baseline::State::Finished => None,
+            s => panic!("Unexpected state: {:?}", s),
+        }
+    }
+
+    fn runs(&mut self) -> bool {
+        self.next_state().is_ok()
+    }
+}
+
+fn run<M: Machine>(mut vm: M) -> Int {
+    let mut sum = 0;
+    while let Some(i) = vm.output() {
+        sum += i;
+    }
+    sum
+}
+
+fn day_02<M: Machine>(template: &M) -> Int {
+    let mut found = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut vm = template.clone();
+            vm.set(1, noun);
+            vm.set(2, verb);
+            if vm.runs() && vm.get(0) == 19690720 {
+                found = 100 * noun + verb;
+            }
+        }
+    }
+    found
+}
+
+fn day_19<M: Machine>(template: &M) -> Int {
+    let mut affected = 0;
+    for x in 0..50 {
+        for y in 0..50 {
+            let mut vm = template.clone();
+            vm.input(x);
+            vm.input(y);
+            affected += run(vm);
+        }
+    }
+    affected
+}
+
+fn main() {
+    let gravity = load(2);
+    let boost = load(9);
+    let beam = load(19);
+
+    // The same programs as words for the baseline, which predates `Program`
+    // keeping them to itself.
+    let words = |program| VM::of(program).mem.to_vec();
+    let (gravity_words, boost_words, beam_words) = (words(&gravity), words(&boost), words(&beam));
+
+    bench("day 02 noun/verb search, baseline", || {
+        day_02(&baseline::VM::of(&gravity_words))
+    });
+    bench("day 02 noun/verb search", || day_02(&VM::of(&gravity)));
+    bench("day 02 noun/verb search, cached", || {
+        day_02(&VM::of(&gravity).with_decode_cache())
+    });
+    bench("day 02 noun/verb search, compiled", || {
+        day_02(&VM::compiled(&gravity))
+    });
+    bench("day 09 sensor boost, baseline", || {
+        let mut vm = baseline::VM::of(&boost_words);
+        vm.input(2);
+        run(vm)
+    });
+    bench("day 09 sensor boost", || {
+        let mut vm = VM::of(&boost);
+        vm.input(2);
+        run(vm)
+    });
+    bench("day 09 sensor boost, cached", || {
+        let mut vm = VM::of(&boost).with_decode_cache();
+        vm.input(2);
+        run(vm)
+    });
+    bench("day 09 sensor boost, compiled", || {
+        let mut vm = VM::compiled(&boost);
+        vm.input(2);
+        run(vm)
+    });
+    bench("day 19 tractor beam, baseline", || {
+        day_19(&baseline::VM::of(&beam_words))
+    });
+    bench("day 19 tractor beam", || day_19(&VM::of(&beam)));
+    bench("day 19 tractor beam, cached", || {
+        day_19(&VM::of(&beam).with_decode_cache())
+    });
+    bench("day 19 tractor beam, compiled", || {
+        day_19(&VM::compiled(&beam))
+    });
+}
@@ -0,0 +1,3 @@
+fn main() {
+    aoc::intcode::debugger::debugger();
+}
@@ -1,4 +1,5 @@
-use crate::intcode::{Program, VM};
+use crate::intcode::symbolic::Symbolic;
+use crate::intcode::{Int, Program, VM};
 use crate::io;
 
 #[no_mangle]
@@ -10,19 +11,32 @@ pub fn day_02() {
     vm.next_state().unwrap();
     io::println!("Mem_0: {}", vm.mem[0]);
 
+    // mem[0] works out to a linear function of the noun and verb, so it can
+    // usually be solved for; if the solver gives up, search every pair instead
+    let noun_verb = Symbolic::new(&program)
+        .with_cell(1, 0..=99)
+        .with_cell(2, 0..=99);
+    let solved = noun_verb
+        .solve(&[12, 2], |run| vec![run.cell(0).equals(19690720)])
+        .map(|values| (values[0], values[1]));
+    let (noun, verb) = match solved.or_else(|| search(&program)) {
+        Some(noun_verb) => noun_verb,
+        None => panic!("No combination found"),
+    };
+    io::println!("Sum: 100 * {} + {} = {}", noun, verb, 100 * noun + verb);
+}
+
+fn search(program: &Program) -> Option<(Int, Int)> {
+    let template = VM::of(program).with_decode_cache();
     for noun in 0..100 {
         for verb in 0..100 {
-            vm = VM::of(&program);
+            let mut vm = template.clone();
             vm.mem[1] = noun;
             vm.mem[2] = verb;
             if vm.next_state().is_ok() && vm.mem[0] == 19690720 {
-                io::println!("Sum: 100 * {} + {} = {}", noun, verb, 100 * noun + verb);
-                return;
+                return Some((noun, verb));
             }
         }
     }
-
-    panic!("No combination found")
+    None
 }
-
-
@@ -1,6 +1,7 @@
 use itertools::Itertools;
 
-use crate::intcode::{Program, State, VM};
+use crate::intcode::pipeline::Pipeline;
+use crate::intcode::{Int, Program, VM};
 use crate::io;
 
 const ACCELERATOR_COUNT: usize = 5;
@@ -8,61 +9,24 @@ const ACCELERATOR_COUNT: usize = 5;
 #[no_mangle]
 pub fn day_07() {
     let program = Program::from_stdin().unwrap();
-    let acs = program.clone().into_fn();
-    io::println!(
-        "Max: {}",
-        vec![0, 1, 2, 3, 4]
-            .iter()
-            .permutations(ACCELERATOR_COUNT)
-            .map(|seq| seq.iter().fold(0, |out, sig| acs(vec![**sig, out])[0]))
-            .max()
-            .unwrap()
-    );
-
-    io::println!(
-        "Max: {}",
-        vec![5, 6, 7, 8, 9]
-            .iter()
+    let amplifiers = || {
+        (0..ACCELERATOR_COUNT)
+            .map(|_| VM::of(&program))
+            .collect_vec()
+    };
+    let max_signal = |phases: Vec<Int>, connect: fn(Vec<VM>) -> Pipeline| {
+        phases
+            .into_iter()
             .permutations(ACCELERATOR_COUNT)
             .map(|seq| {
-                let mut vms = seq
-                    .iter()
-                    .map(|sig| {
-                        let mut vm = VM::of(&program);
-                        vm.input(**sig);
-                        vm
-                    })
-                    .collect_vec();
-                vms[0].input(0);
-                loop {
-                    let mut deadlock_check = true;
-                    for accelerator in 0..ACCELERATOR_COUNT {
-                        loop {
-                            let state = vms.get_mut(accelerator).unwrap().next_state().unwrap();
-                            match state {
-                                State::Outputting(i) => {
-                                    let next_vm =
-                                        vms.get_mut((accelerator + 1) % ACCELERATOR_COUNT).unwrap();
-                                    if next_vm.is_finished() {
-                                        return i;
-                                    }
-                                    next_vm.input(i);
-                                    deadlock_check = false;
-                                }
-                                State::AwaitingInput => break,
-                                State::Finished => break,
-                            }
-                        }
-                    }
-                    if deadlock_check {
-                        // take that, halting problem
-                        panic!("Deadlock detected!");
-                    }
-                }
+                let mut pipeline = connect(amplifiers()).with_phases(seq);
+                pipeline.input(0, 0).unwrap();
+                pipeline.run().unwrap()
             })
             .max()
             .unwrap()
-    );
-}
-
+    };
 
+    io::println!("Max: {}", max_signal(vec![0, 1, 2, 3, 4], Pipeline::series));
+    io::println!("Max: {}", max_signal(vec![5, 6, 7, 8, 9], Pipeline::ring));
+}
@@ -99,6 +99,7 @@ fn paint(program: &Program, painted: &mut HashMap<Vector, Colour>) {
                 }
             },
             State::Finished => break,
+            s => panic!("Unexpected state: {:?}", s),
         }
     }
 }
@@ -2,13 +2,14 @@ use crate::intcode::{Int, Program, State, VM};
 use crate::io;
 use itertools::Itertools;
 use std::collections::HashMap;
+use std::env;
+use std::fs::File;
 use std::iter;
 
 fn display(vm: &mut VM, board: &mut HashMap<(Int, Int), Int>) {
     for (x, y, id) in iter::from_fn(|| match vm.next_state().unwrap() {
-        State::AwaitingInput => None,
         State::Outputting(i) => Some(i),
-        State::Finished => None,
+        _ => None,
     })
     .tuples::<(Int, Int, Int)>()
     {
@@ -39,6 +40,10 @@ fn print_board(board: &HashMap<(Int, Int), Int>, w: Int, h: Int) {
 
 const PRINT_BOARD: bool = false;
 
+// Names the environment variable that, set to a path, records the game there,
+// to replay with intdbg later.
+const TRANSCRIPT: &str = "INTCODE_TRANSCRIPT";
+
 const WIDTH: Int = 64;
 const HEIGHT: Int = 24;
 
@@ -53,6 +58,9 @@ pub fn day_13() {
 
     let mut game = VM::of(&program);
     game.mem[0] = 2;
+    // free play goes in the transcript too, for replaying from the program
+    let transcript = env::var_os(TRANSCRIPT);
+    let recording = transcript.as_ref().map(|_| game.record_from(&program));
     board = HashMap::new();
 
     display(&mut game, &mut board);
@@ -69,6 +77,10 @@ pub fn day_13() {
         }
     }
     io::println!("Score: {}", board.get(&(-1, 0)).unwrap_or(&0));
+    if let (Some(path), Some(recording)) = (transcript, recording) {
+        let mut file = File::create(path).unwrap();
+        recording.lock().unwrap().save(&mut file).unwrap();
+    }
 }
 
 
@@ -12,8 +12,9 @@ fn display_ascii(img: &Vec<Vec<char>>) {
 pub fn day_17() {
     let ascii = Program::from_stdin().unwrap();
     let mut img = VM::of(&ascii)
-        .map(|i| i as u8 as char)
-        .join("")
+        .into_ascii()
+        .read()
+        .unwrap()
         .trim()
         .split("\n")
         .map(|s| s.chars().collect_vec())
@@ -93,12 +94,14 @@ pub fn day_17() {
 
     // A----------- A----------- C------------ B------------------ C------------ B------------------ C------------ A----------- B------------------ A-----------
     // R,8,L,12,R,8,R,8,L,12,R,8,L,10,L,10,R,8,L,12,L,12,L,10,R,10,L,10,L,10,R,8,L,12,L,12,L,10,R,10,L,10,L,10,R,8,R,8,L,12,R,8,L,12,L,12,L,10,R,10,R,8,L,12,R,8
-    vm.input_ascii("A,A,C,B,C,B,C,A,B,A\n");
-    vm.input_ascii("R,8,L,12,R,8\n"); // A
-    vm.input_ascii("L,12,L,12,L,10,R,10\n"); // B
-    vm.input_ascii("L,10,L,10,R,8\n"); // C
-    vm.input_ascii("n\n");
-    io::println!("Dust: {}", vm.last().unwrap());
+    let mut robot = vm.into_ascii();
+    robot.send_line("A,A,C,B,C,B,C,A,B,A").unwrap();
+    robot.send_line("R,8,L,12,R,8").unwrap(); // A
+    robot.send_line("L,12,L,12,L,10,R,10").unwrap(); // B
+    robot.send_line("L,10,L,10,R,8").unwrap(); // C
+    robot.send_line("n").unwrap();
+    robot.read().unwrap();
+    io::println!("Dust: {}", robot.value().unwrap());
 }
 
 
@@ -4,11 +4,12 @@ use crate::io;
 #[no_mangle]
 pub fn day_21() {
     let spring_droid = Program::from_stdin().unwrap();
-    let mut vm;
+    let mut droid;
 
-    vm = VM::of(&spring_droid);
-    vm.input_ascii(
-        "\
+    droid = VM::of(&spring_droid).into_ascii();
+    droid
+        .send(
+            "\
 NOT A J
 NOT B T
 OR T J
@@ -17,12 +18,15 @@ OR T J
 AND D J
 WALK
 ",
-    );
-    io::println!("Damage: {}", vm.last().unwrap());
+        )
+        .unwrap();
+    droid.read().unwrap();
+    io::println!("Damage: {}", droid.value().unwrap());
 
-    vm = VM::of(&spring_droid);
-    vm.input_ascii(
-        "\
+    droid = VM::of(&spring_droid).into_ascii();
+    droid
+        .send(
+            "\
 NOT A J
 NOT B T
 OR T J
@@ -35,8 +39,10 @@ OR E T
 AND T J
 RUN
 ",
-    );
-    io::println!("Damage: {}", vm.last().unwrap());
+        )
+        .unwrap();
+    droid.read().unwrap();
+    io::println!("Damage: {}", droid.value().unwrap());
 }
 
 
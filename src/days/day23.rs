@@ -1,121 +1,52 @@
-use crate::intcode::{Int, Program, State, VM};
-use crate::io;
-use std::collections::HashMap;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use crate::intcode::network::{Nat, Network};
+use crate::intcode::profile::Profile;
+use crate::intcode::{Int, Program, VM};
+use crate::io::{self, Write};
+use std::env;
+use std::fs::File;
 
 const COMPUTERS: Int = 50;
 const NAT_ADDRESS: Int = 255;
+const TIME_SLICE: usize = 1000;
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-struct Packet {
-    x: Int,
-    y: Int,
-}
-
-struct NetworkComputer {
-    chan: Receiver<Packet>,
-    vm: VM,
-}
-
-impl NetworkComputer {
-    fn new(address: Int, nic: &Program, chan: Receiver<Packet>) -> Self {
-        let mut vm = VM::of(nic);
-        vm.input(address);
-        NetworkComputer { chan, vm }
-    }
-
-    fn advance(&mut self, addresses: &HashMap<Int, Sender<Packet>>) -> bool {
-        match self.vm.next_state().unwrap() {
-            State::AwaitingInput => match self.chan.try_recv() {
-                Ok(packet) => {
-                    self.vm.input(packet.x);
-                    self.vm.input(packet.y);
-                    true
-                }
-                Err(_) => {
-                    self.vm.input(-1);
-                    false
-                }
-            },
-            State::Outputting(dest) => {
-                if let State::Outputting(x) = self.vm.next_state().unwrap() {
-                    if let State::Outputting(y) = self.vm.next_state().unwrap() {
-                        match addresses.get(&dest) {
-                            None => {}
-                            Some(dest) => {
-                                dest.send(Packet { x, y }).unwrap();
-                            }
-                        }
-                        true
-                    } else {
-                        panic!()
-                    }
-                } else {
-                    panic!()
-                }
-            }
-            State::Finished => false,
-        }
-    }
-}
+// Names the environment variable that, set to a path, profiles the NICs all
+// together, writing the report and listing there and the stacks for a flame
+// graph to the same path plus `.folded`.
+const PROFILE: &str = "INTCODE_PROFILE";
 
 #[no_mangle]
 pub fn day_23() {
     let nic = Program::from_stdin().unwrap();
-    let mut addresses = HashMap::new();
-
-    let (sender, nat) = channel::<Packet>();
-    addresses.insert(NAT_ADDRESS, sender);
-
-    let mut computers = Vec::new();
+    let mut network = Network::new(3)
+        .with_idle_input(-1)
+        .with_time_slice(TIME_SLICE);
+    let path = env::var_os(PROFILE);
+    let mut profiles = Vec::new();
     for address in 0..COMPUTERS {
-        let (sender, receiver) = channel();
-        addresses.insert(address, sender);
-        computers.push(NetworkComputer::new(address, &nic, receiver));
-    }
-
-    run_until_idle(&mut computers, &addresses);
-    let mut last_packet = nat.try_recv().unwrap();
-    io::println!("Packet: Y={}", last_packet.y);
-
-    if let Some(p) = nat.try_iter().last() {
-        last_packet = p;
-    }
-    let mut last_sent;
-    let nat_dest = addresses.get(&0).unwrap();
-
-    loop {
-        last_sent = last_packet;
-        nat_dest.send(last_packet).unwrap();
-        run_until_idle(&mut computers, &addresses);
-        if let Some(p) = nat.try_iter().last() {
-            last_packet = p;
-        }
-        if last_packet == last_sent {
-            break;
+        let mut vm = VM::of(&nic);
+        vm.input(address);
+        if path.is_some() {
+            profiles.push(vm.profile());
         }
+        network.add(address, vm);
     }
 
-    io::println!("Duplicate: Y={}", last_packet.y);
-}
-
-const MAX_IDLES: u32 = 2;
+    let mut nat = Nat::new(NAT_ADDRESS, 0);
+    network.run(&mut nat).unwrap();
 
-fn run_until_idle(computers: &mut Vec<NetworkComputer>, addresses: &HashMap<Int, Sender<Packet>>) {
-    let mut idle_counter = 0;
-    while idle_counter < MAX_IDLES {
-        let mut idle = true;
-        for computer in &mut *computers {
-            while computer.advance(addresses) {
-                idle = false;
-            }
-        }
-        if idle {
-            idle_counter += 1;
-        } else {
-            idle_counter = 0;
+    io::println!("Packet: Y={}", nat.first().unwrap().payload[1]);
+    io::println!("Duplicate: Y={}", nat.sent().last().unwrap().payload[1]);
+    if let Some(path) = path {
+        let mut profile = Profile::default();
+        for nic in profiles {
+            profile.merge(&nic.lock().unwrap());
         }
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "{}", profile).unwrap();
+        profile.write_listing(&mut file).unwrap();
+        let mut folded = path;
+        folded.push(".folded");
+        let mut file = File::create(folded).unwrap();
+        profile.write_collapsed(&mut file).unwrap();
     }
 }
-
-
@@ -0,0 +1,141 @@
+use std::error;
+use std::fmt::{self, Debug, Display, Formatter};
+
+use crate::intcode::word::Word;
+use crate::intcode::{ExecError, Int, State, VM};
+
+// A VM that talks ASCII. Text is read a line, a prompt or a whole burst at a
+// time, and stops early at any output that isn't ASCII, which is kept aside to
+// be picked up with `value` - that's how most programs report their answer.
+pub struct Ascii<W = Int> {
+    vm: VM<W>,
+    value: Option<W>,
+}
+
+pub enum AsciiError<W = Int> {
+    NonAscii(char),
+    Exec(ExecError<W>),
+}
+
+impl<W: Word> VM<W> {
+    pub fn into_ascii(self) -> Ascii<W> {
+        Ascii {
+            vm: self,
+            value: None,
+        }
+    }
+}
+
+impl<W: Word> Ascii<W> {
+    pub fn vm(&self) -> &VM<W> {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM<W> {
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> VM<W> {
+        self.vm
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.vm.is_finished()
+    }
+
+    // Queues up text as input. Nothing is sent unless all of it is ASCII.
+    pub fn send(&mut self, text: &str) -> Result<(), AsciiError<W>> {
+        if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+            return Err(AsciiError::NonAscii(c));
+        }
+        self.vm.input_ascii(text);
+        Ok(())
+    }
+
+    pub fn send_line(&mut self, line: &str) -> Result<(), AsciiError<W>> {
+        self.send(line)?;
+        self.send("\n")
+    }
+
+    // Reads all the text output before the VM next waits for input, finishes or
+    // runs out of fuel.
+    pub fn read(&mut self) -> Result<String, AsciiError<W>> {
+        self.read_while(|_| true)
+    }
+
+    // Reads up to and including the prompt.
+    pub fn read_until(&mut self, prompt: &str) -> Result<String, AsciiError<W>> {
+        self.read_while(|text| !text.ends_with(prompt))
+    }
+
+    // Reads a line without its newline, or `None` if there's no more text.
+    pub fn read_line(&mut self) -> Result<Option<String>, AsciiError<W>> {
+        let mut line = self.read_until("\n")?;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    // Takes the last non-ASCII output read.
+    pub fn value(&mut self) -> Option<W> {
+        self.value.take()
+    }
+
+    fn read_while<F: Fn(&str) -> bool>(&mut self, more: F) -> Result<String, AsciiError<W>> {
+        let mut text = String::new();
+        while more(&text) {
+            let w = loop {
+                match self.vm.next_state()? {
+                    State::Outputting(w) => break w,
+                    State::AwaitingInput | State::Finished | State::OutOfFuel => return Ok(text),
+                    State::Breakpoint(_) | State::Watch(_, _) => (),
+                }
+            };
+            match w.to_u8().filter(u8::is_ascii) {
+                Some(b) => text.push(b as char),
+                None => {
+                    self.value = Some(w);
+                    break;
+                }
+            }
+        }
+        Ok(text)
+    }
+}
+
+impl<W> From<ExecError<W>> for AsciiError<W> {
+    fn from(e: ExecError<W>) -> Self {
+        AsciiError::Exec(e)
+    }
+}
+
+impl<W: Word> Debug for AsciiError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AsciiError::NonAscii(c) => write!(f, "NonAscii({:?})", c),
+            AsciiError::Exec(e) => write!(f, "Exec({:?})", e),
+        }
+    }
+}
+
+impl<W: Word> Display for AsciiError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AsciiError::NonAscii(c) => write!(f, "{:?} isn't an ASCII character", c),
+            AsciiError::Exec(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl<W: Word> error::Error for AsciiError<W> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AsciiError::Exec(e) => Some(e),
+            _ => None,
+        }
+    }
+}
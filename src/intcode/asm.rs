@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::intcode::{Insn, Int, Mode, Program};
+
+// Source syntax, one statement per line:
+//
+//   label:  ADD #1, [x], rb+2   ; comment
+//           PUSH [x]
+//           CALL #func
+//   x:      .data 0, 'a', x+1
+//   msg:    .string "hello\n"
+//
+// Operands are `[expr]` (position), `#expr` (immediate) or `rb+expr` (relative),
+// where an expression is a sum of integers, 'c' characters and labels that
+// has to fit in a word.
+// PUSH, POP, CALL and RET expand to stack operations through the relative base.
+
+#[derive(Debug)]
+pub enum AssembleError {
+    UnknownMnemonic(usize, String),
+    UnknownDirective(usize, String),
+    OperandCount(usize, Insn, usize),
+    BadOperand(usize, String),
+    BadExpression(usize, String),
+    BadString(usize, String),
+    ImmediateWrite(usize, String),
+    DuplicateLabel(usize, String),
+    UndefinedLabel(usize, String),
+    // An expression's value doesn't fit in a word.
+    Overflow(usize),
+}
+
+type AsmResult<T> = Result<T, AssembleError>;
+
+#[derive(Clone, Debug)]
+enum Term {
+    Num(Int),
+    Label(String),
+}
+
+#[derive(Clone, Debug)]
+struct Expr {
+    terms: Vec<(Int, Term)>,
+}
+
+#[derive(Clone, Debug)]
+struct Operand {
+    mode: Mode,
+    expr: Expr,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Macro {
+    Push,
+    Pop,
+    Call,
+    Ret,
+}
+
+struct Assembler {
+    labels: HashMap<String, usize>,
+    words: Vec<(usize, Expr)>,
+}
+
+pub fn assemble(source: &str) -> AsmResult<Program> {
+    let mut asm = Assembler {
+        labels: HashMap::new(),
+        words: Vec::new(),
+    };
+    for (i, line) in source.lines().enumerate() {
+        asm.statement(i + 1, strip_comment(line))?;
+    }
+    let instructions = asm
+        .words
+        .iter()
+        .map(|(line, expr)| asm.eval(*line, expr))
+        .collect::<AsmResult<Vec<Int>>>()?;
+    Ok(Program { instructions })
+}
+
+impl Expr {
+    fn num(n: Int) -> Self {
+        Expr {
+            terms: vec![(1, Term::Num(n))],
+        }
+    }
+}
+
+impl Operand {
+    fn imm(n: Int) -> Self {
+        Operand {
+            mode: Mode::Immediate,
+            expr: Expr::num(n),
+        }
+    }
+
+    fn rel(n: Int) -> Self {
+        Operand {
+            mode: Mode::Relative,
+            expr: Expr::num(n),
+        }
+    }
+}
+
+impl Macro {
+    fn named(name: &str) -> Option<Self> {
+        Some(match name {
+            "PUSH" => Macro::Push,
+            "POP" => Macro::Pop,
+            "CALL" => Macro::Call,
+            "RET" => Macro::Ret,
+            _ => return None,
+        })
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Macro::Push | Macro::Pop | Macro::Call => 1,
+            Macro::Ret => 0,
+        }
+    }
+}
+
+impl Assembler {
+    fn addr(&self) -> usize {
+        self.words.len()
+    }
+
+    fn statement(&mut self, line: usize, mut text: &str) -> AsmResult<()> {
+        loop {
+            text = text.trim();
+            let end = text.find(|c: char| !is_ident_char(c)).unwrap_or(text.len());
+            if end == 0 || !text[end..].starts_with(':') {
+                break;
+            }
+            let label = &text[..end];
+            if self.labels.insert(label.to_owned(), self.addr()).is_some() {
+                return Err(AssembleError::DuplicateLabel(line, label.to_owned()));
+            }
+            text = &text[end + 1..];
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (head, rest) = match text.find(char::is_whitespace) {
+            Some(i) => (&text[..i], text[i..].trim()),
+            None => (text, ""),
+        };
+        if head.starts_with('.') {
+            return self.directive(line, head, rest);
+        }
+
+        let name = head.to_uppercase().replace('_', "");
+        let operands = split_operands(rest)
+            .iter()
+            .map(|s| parse_operand(line, s))
+            .collect::<AsmResult<Vec<Operand>>>()?;
+        if let Some(mac) = Macro::named(&name) {
+            if operands.len() != mac.arity() {
+                return Err(AssembleError::BadOperand(line, text.to_owned()));
+            }
+            return self.expand(line, mac, operands);
+        }
+        let insn = (1..=9)
+            .chain(Some(99))
+            .filter_map(|op| Insn::of(op).ok())
+            .find(|insn| insn.mnemonic().replace('_', "") == name)
+            .ok_or_else(|| AssembleError::UnknownMnemonic(line, head.to_owned()))?;
+        self.emit(line, insn, operands)
+    }
+
+    fn directive(&mut self, line: usize, name: &str, rest: &str) -> AsmResult<()> {
+        match name {
+            ".data" => {
+                for value in split_operands(rest) {
+                    let expr = parse_expr(line, &value)?;
+                    self.words.push((line, expr));
+                }
+            }
+            ".string" => {
+                for c in parse_string(line, rest)? {
+                    self.words.push((line, Expr::num(c as Int)));
+                }
+            }
+            _ => return Err(AssembleError::UnknownDirective(line, name.to_owned())),
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, line: usize, insn: Insn, operands: Vec<Operand>) -> AsmResult<()> {
+        if operands.len() != insn.arity() {
+            return Err(AssembleError::OperandCount(line, insn, operands.len()));
+        }
+        if insn.writes() && operands.last().unwrap().mode == Mode::Immediate {
+            return Err(AssembleError::ImmediateWrite(
+                line,
+                insn.mnemonic().to_owned(),
+            ));
+        }
+        let word = operands
+            .iter()
+            .rev()
+            .fold(0, |acc, op| acc * 10 + op.mode.digit() as Int)
+            * 100
+            + insn.opcode() as Int;
+        self.words.push((line, Expr::num(word)));
+        for op in operands {
+            self.words.push((line, op.expr));
+        }
+        Ok(())
+    }
+
+    fn expand(&mut self, line: usize, mac: Macro, mut operands: Vec<Operand>) -> AsmResult<()> {
+        match mac {
+            Macro::Push => {
+                let src = operands.pop().unwrap();
+                self.emit(line, Insn::Add, vec![src, Operand::imm(0), Operand::rel(0)])?;
+                self.emit(line, Insn::SetBase, vec![Operand::imm(1)])
+            }
+            Macro::Pop => {
+                let dst = operands.pop().unwrap();
+                self.emit(line, Insn::SetBase, vec![Operand::imm(-1)])?;
+                self.emit(line, Insn::Add, vec![Operand::rel(0), Operand::imm(0), dst])
+            }
+            Macro::Call => {
+                let target = operands.pop().unwrap();
+                // ADD (4) + SET_BASE (2) + JUMP_IF_TRUE (3)
+                let ret = (self.addr() + 9) as Int;
+                self.emit(
+                    line,
+                    Insn::Add,
+                    vec![Operand::imm(ret), Operand::imm(0), Operand::rel(0)],
+                )?;
+                self.emit(line, Insn::SetBase, vec![Operand::imm(1)])?;
+                self.emit(line, Insn::JumpIfTrue, vec![Operand::imm(1), target])
+            }
+            Macro::Ret => {
+                self.emit(line, Insn::SetBase, vec![Operand::imm(-1)])?;
+                self.emit(
+                    line,
+                    Insn::JumpIfTrue,
+                    vec![Operand::imm(1), Operand::rel(0)],
+                )
+            }
+        }
+    }
+
+    fn eval(&self, line: usize, expr: &Expr) -> AsmResult<Int> {
+        expr.terms.iter().try_fold(0, |acc: Int, (sign, term)| {
+            let val = match term {
+                Term::Num(n) => *n,
+                Term::Label(label) => *self
+                    .labels
+                    .get(label)
+                    .ok_or_else(|| AssembleError::UndefinedLabel(line, label.clone()))?
+                    as Int,
+            };
+            sign.checked_mul(val)
+                .and_then(|val| acc.checked_add(val))
+                .ok_or(AssembleError::Overflow(line))
+        })
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (None, ';') => return &line[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if q == c => quote = None,
+            _ => (),
+        }
+    }
+    line
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, ',') => {
+                ret.push(current.trim().to_owned());
+                current.clear();
+                continue;
+            }
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(_), '\\') => {
+                current.push(c);
+                if let Some(c) = chars.next() {
+                    current.push(c);
+                }
+                continue;
+            }
+            (Some(q), c) if q == c => quote = None,
+            _ => (),
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() || !ret.is_empty() {
+        ret.push(current.trim().to_owned());
+    }
+    ret
+}
+
+fn parse_operand(line: usize, text: &str) -> AsmResult<Operand> {
+    let (mode, expr) = if let Some(rest) = text.strip_prefix('#') {
+        (Mode::Immediate, rest)
+    } else if text.starts_with('[') && text.ends_with(']') {
+        (Mode::Position, &text[1..text.len() - 1])
+    } else if text.starts_with("rb") && !text[2..].starts_with(is_ident_char) {
+        let rest = text[2..].trim();
+        if rest.is_empty() {
+            return Ok(Operand::rel(0));
+        }
+        (Mode::Relative, rest)
+    } else {
+        return Err(AssembleError::BadOperand(line, text.to_owned()));
+    };
+    Ok(Operand {
+        mode,
+        expr: parse_expr(line, expr)?,
+    })
+}
+
+fn parse_expr(line: usize, text: &str) -> AsmResult<Expr> {
+    let bad = || AssembleError::BadExpression(line, text.to_owned());
+    let mut chars = text.char_indices().peekable();
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    loop {
+        skip_spaces(&mut chars);
+        match chars.peek() {
+            Some((_, '-')) => {
+                sign = -sign;
+                chars.next();
+                continue;
+            }
+            Some((_, '+')) => {
+                chars.next();
+                continue;
+            }
+            _ => (),
+        }
+        let term = match chars.next() {
+            Some((_, '\'')) => {
+                let c = parse_char(&mut chars).ok_or_else(bad)?;
+                match chars.next() {
+                    Some((_, '\'')) => Term::Num(c as Int),
+                    _ => return Err(bad()),
+                }
+            }
+            Some((start, c)) if is_ident_char(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !is_ident_char(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let word = &text[start..end];
+                if c.is_ascii_digit() {
+                    // parsed with its sign, so the most negative word can be
+                    // written as it is
+                    let n = match sign {
+                        -1 => format!("-{}", word).parse(),
+                        _ => word.parse(),
+                    };
+                    sign = 1;
+                    Term::Num(n.map_err(|_| bad())?)
+                } else {
+                    Term::Label(word.to_owned())
+                }
+            }
+            _ => return Err(bad()),
+        };
+        terms.push((sign, term));
+        skip_spaces(&mut chars);
+        sign = match chars.next() {
+            None => return Ok(Expr { terms }),
+            Some((_, '+')) => 1,
+            Some((_, '-')) => -1,
+            _ => return Err(bad()),
+        };
+    }
+}
+
+fn skip_spaces(chars: &mut Peekable<CharIndices>) {
+    while chars.peek().is_some_and(|(_, c)| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn parse_char(chars: &mut Peekable<CharIndices>) -> Option<char> {
+    Some(match chars.next()?.1 {
+        '\\' => match chars.next()?.1 {
+            'n' => '\n',
+            't' => '\t',
+            '0' => '\0',
+            c => c,
+        },
+        c => c,
+    })
+}
+
+fn parse_string(line: usize, text: &str) -> AsmResult<Vec<char>> {
+    let bad = || AssembleError::BadString(line, text.to_owned());
+    let mut chars = text.char_indices().peekable();
+    if chars.next().map(|(_, c)| c) != Some('"') {
+        return Err(bad());
+    }
+    let mut ret = Vec::new();
+    loop {
+        match chars.peek() {
+            None => return Err(bad()),
+            Some((_, '"')) => break,
+            _ => ret.push(parse_char(&mut chars).ok_or_else(bad)?),
+        }
+    }
+    chars.next();
+    if chars.next().is_some() {
+        return Err(bad());
+    }
+    Ok(ret)
+}
@@ -0,0 +1,323 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{self, Write};
+use std::iter;
+
+use itertools::Itertools;
+
+use crate::intcode::disasm::{Line, Operand};
+use crate::intcode::{Insn, Int, Mode};
+
+// The control-flow graph of a program, recovered by following its jumps from
+// address 0 rather than sweeping memory, so data mixed in with code is left
+// alone. Only jumps to immediate addresses can be followed.
+//
+// Calls are recognised the way compiled Intcode makes them: a return address
+// pushed onto the relative-base stack, then an unconditional jump, so
+//
+//   ADD #13, #0, rb+0
+//   JUMP_IF_TRUE #1, #1424
+//
+// at address 10 is a call to 1424 that returns to 13. A jump through a
+// relative operand, as in `JUMP_IF_TRUE #1, rb+0`, is taken to be a return.
+
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    blocks: BTreeMap<usize, Block>,
+    functions: BTreeSet<usize>,
+    self_modifying: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub start: usize,
+    pub lines: Vec<Line>,
+    pub exit: Exit,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Exit {
+    // Runs on into the block at the address.
+    Fallthrough(usize),
+    Jump(usize),
+    // Where the branch goes if it's taken, and where it goes if not.
+    Branch(usize, usize),
+    // The function called, if it's not through a pointer, and where it returns to.
+    Call(Option<usize>, usize),
+    Return,
+    // A jump to a computed address, and where it goes if it's not taken.
+    Indirect(Option<usize>),
+    Halt,
+}
+
+pub fn analyse(mem: &[Int]) -> Cfg {
+    let mut cfg = analyse_from(mem, iter::once(0));
+    cfg.functions.insert(0);
+    cfg
+}
+
+// Like `analyse`, but following the jumps from each of `entries`, for when
+// more is known about where the program goes than can be seen from address 0.
+pub fn analyse_from<I: IntoIterator<Item = usize>>(mem: &[Int], entries: I) -> Cfg {
+    let mut lines = BTreeMap::new();
+    // how control leaves each instruction that doesn't carry on to the next,
+    // worked out once while following the code, since whether a jump is a
+    // call depends on what was pushed before it, maybe in another block
+    let mut exits = BTreeMap::new();
+    let mut work = entries.into_iter().collect_vec();
+    let mut leaders = work.iter().copied().collect::<BTreeSet<_>>();
+    let mut functions = BTreeSet::new();
+    while let Some(mut addr) = work.pop() {
+        let mut pushed = HashSet::new();
+        loop {
+            if lines.contains_key(&addr) {
+                // ran into code that's already been seen, which starts a block
+                leaders.insert(addr);
+                break;
+            }
+            let line = match Line::at(mem, addr) {
+                Some(line @ Line::Insn { .. }) => line,
+                _ => break,
+            };
+            let exit = flow(&line, &mut pushed);
+            let next = addr + line.size();
+            lines.insert(addr, line);
+            match exit {
+                Some(exit) => {
+                    exits.insert(addr, exit);
+                    if let Exit::Call(Some(callee), _) = exit {
+                        functions.insert(callee);
+                    }
+                    for next in exit.successors() {
+                        leaders.insert(next);
+                        work.push(next);
+                    }
+                    break;
+                }
+                None => addr = next,
+            }
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in leaders.iter().filter(|addr| lines.contains_key(addr)) {
+        let mut block = Vec::new();
+        let mut addr = start;
+        let exit = loop {
+            let line = &lines[&addr];
+            block.push(line.clone());
+            let exit = exits.get(&addr);
+            addr += line.size();
+            match exit {
+                Some(&exit) => break exit,
+                None if leaders.contains(&addr) || !lines.contains_key(&addr) => {
+                    break Exit::Fallthrough(addr)
+                }
+                None => (),
+            }
+        };
+        blocks.insert(
+            start,
+            Block {
+                start,
+                lines: block,
+                exit,
+            },
+        );
+    }
+
+    let code = |cell: usize| match lines.range(..=cell).next_back() {
+        Some((&addr, line)) => cell < addr + line.size(),
+        None => false,
+    };
+    let self_modifying = lines
+        .values()
+        .filter_map(|line| match line {
+            Line::Insn { insn, operands, .. } if insn.writes() => match operands.last() {
+                Some(Operand {
+                    mode: Mode::Position,
+                    value,
+                }) if *value >= 0 && code(*value as usize) => Some((line.addr(), *value as usize)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    Cfg {
+        blocks,
+        functions,
+        self_modifying,
+    }
+}
+
+// How control leaves an instruction, if it does anything but carry on to the
+// next. `pushed` collects constants written through the relative base, to spot
+// return addresses.
+fn flow(line: &Line, pushed: &mut HashSet<Int>) -> Option<Exit> {
+    let (addr, insn, operands) = match line {
+        Line::Insn {
+            addr,
+            insn,
+            operands,
+            ..
+        } => (*addr, *insn, operands),
+        Line::Data { .. } => return Some(Exit::Halt),
+    };
+    let imm = |i: usize| match operands[i] {
+        Operand {
+            mode: Mode::Immediate,
+            value,
+        } => Some(value),
+        _ => None,
+    };
+    let next = addr + line.size();
+    match insn {
+        Insn::Add | Insn::Mul if operands[2].mode == Mode::Relative => {
+            if let (Some(a), Some(b)) = (imm(0), imm(1)) {
+                let value = match insn {
+                    Insn::Add => a.checked_add(b),
+                    _ => a.checked_mul(b),
+                };
+                pushed.extend(value);
+            }
+            None
+        }
+        Insn::JumpIfTrue | Insn::JumpIfFalse => {
+            let taken = imm(0).map(|cond| (cond != 0) == (insn == Insn::JumpIfTrue));
+            let target = imm(1).filter(|&t| t >= 0).map(|t| t as usize);
+            match (taken, target, operands[1].mode) {
+                (Some(false), _, _) => None,
+                (Some(true), t, _) if pushed.contains(&(next as Int)) => Some(Exit::Call(t, next)),
+                (Some(true), Some(t), _) => Some(Exit::Jump(t)),
+                (None, Some(t), _) => Some(Exit::Branch(t, next)),
+                (Some(true), None, Mode::Relative) => Some(Exit::Return),
+                (Some(true), None, _) => Some(Exit::Indirect(None)),
+                (None, None, _) => Some(Exit::Indirect(Some(next))),
+            }
+        }
+        Insn::End => Some(Exit::Halt),
+        _ => None,
+    }
+}
+
+impl Exit {
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Fallthrough(next) | Exit::Jump(next) | Exit::Indirect(Some(next)) => vec![next],
+            Exit::Branch(taken, next) | Exit::Call(Some(taken), next) => vec![taken, next],
+            Exit::Call(None, next) => vec![next],
+            Exit::Return | Exit::Indirect(None) | Exit::Halt => vec![],
+        }
+    }
+}
+
+impl Cfg {
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&Block> {
+        self.blocks.get(&start)
+    }
+
+    // The entry point and everything called from it.
+    pub fn functions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.functions.iter().copied()
+    }
+
+    // Instructions that write into code, with the address they write to.
+    pub fn self_modifying(&self) -> &[(usize, usize)] {
+        &self.self_modifying
+    }
+
+    // Writes the graph for Graphviz. Blocks that get written to are drawn red,
+    // and calls and returns are dashed.
+    pub fn write_dot<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        let modified = self
+            .self_modifying
+            .iter()
+            .map(|&(_, cell)| cell)
+            .collect_vec();
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "    node [shape=box, fontname=monospace];")?;
+        for block in self.blocks.values() {
+            let end = block.start + block.lines.iter().map(Line::size).sum::<usize>();
+            let label = block
+                .lines
+                .iter()
+                .map(|line| format!("{}\\l", line).replace('"', "\\\""))
+                .join("");
+            let mut attrs = format!("label=\"{}\"", label);
+            if self.functions.contains(&block.start) {
+                attrs += ", style=bold";
+            }
+            if modified
+                .iter()
+                .any(|cell| (block.start..end).contains(cell))
+            {
+                attrs += ", color=red";
+            }
+            if block.exit == Exit::Halt {
+                attrs += ", peripheries=2";
+            }
+            writeln!(out, "    b{} [{}];", block.start, attrs)?;
+
+            let edge =
+                |to: usize, attrs: &str| format!("    b{} -> b{} [{}];", block.start, to, attrs);
+            match block.exit {
+                Exit::Fallthrough(next) | Exit::Jump(next) => writeln!(out, "{}", edge(next, ""))?,
+                Exit::Branch(taken, next) => {
+                    writeln!(out, "{}", edge(taken, "label=taken"))?;
+                    writeln!(out, "{}", edge(next, "label=\"not taken\""))?;
+                }
+                Exit::Call(callee, next) => {
+                    match callee {
+                        Some(callee) => {
+                            writeln!(out, "{}", edge(callee, "label=call, style=dashed"))?
+                        }
+                        None => {
+                            writeln!(out, "    b{}_ind [label=\"?\", shape=circle];", block.start)?;
+                            writeln!(
+                                out,
+                                "    b{0} -> b{0}_ind [label=call, style=dashed];",
+                                block.start
+                            )?;
+                        }
+                    }
+                    writeln!(out, "{}", edge(next, "label=returns, style=dotted"))?;
+                }
+                Exit::Return => {
+                    writeln!(
+                        out,
+                        "    b{}_ret [label=return, shape=plaintext];",
+                        block.start
+                    )?;
+                    writeln!(out, "    b{0} -> b{0}_ret [style=dashed];", block.start)?;
+                }
+                Exit::Indirect(next) => {
+                    writeln!(out, "    b{}_ind [label=\"?\", shape=circle];", block.start)?;
+                    writeln!(out, "    b{0} -> b{0}_ind;", block.start)?;
+                    if let Some(next) = next {
+                        writeln!(out, "{}", edge(next, "label=\"not taken\""))?;
+                    }
+                }
+                Exit::Halt => (),
+            }
+        }
+        // targets that didn't decode as code
+        for to in self
+            .blocks
+            .values()
+            .flat_map(|block| block.exit.successors())
+            .filter(|to| !self.blocks.contains_key(to))
+            .unique()
+        {
+            writeln!(
+                out,
+                "    b{0} [label=\"{0}: not code\", shape=plaintext];",
+                to
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,211 @@
+use itertools::Itertools;
+
+use crate::intcode::cfg;
+use crate::intcode::memory::Memory;
+use crate::intcode::word::Word;
+use crate::intcode::{Decoded, Error, Insn, Int, Mode, State, VMResult, VM};
+
+// A program translated ahead of time into closures, one per instruction, with
+// its modes and operands baked in so nothing is decoded while it runs. Only
+// instructions the control-flow graph reaches get compiled. The interpreter
+// runs the rest, along with any instruction whose cells have been written to
+// since, and everything while there's a tracer, breakpoint, watchpoint or undo
+// log set.
+
+type Op<W> = Box<dyn Fn(&mut VM<W>) -> VMResult<Option<State<W>>> + Send + Sync>;
+type Load<W> = Box<dyn Fn(&VM<W>) -> VMResult<W> + Send + Sync>;
+type Store<W> = Box<dyn Fn(&mut VM<W>, W) -> VMResult<()> + Send + Sync>;
+
+pub(super) struct Code<W> {
+    ops: Vec<Option<Op<W>>>,
+}
+
+impl<W: Word> Code<W> {
+    // Also returns which cells hold compiled instructions, as `Memory` expects
+    // them for its code guard.
+    pub(super) fn compile(mem: &Memory<W>) -> (Self, Vec<u8>) {
+        let ints = mem
+            .words()
+            .iter()
+            .map(|w| w.to_int().unwrap_or(-1))
+            .collect_vec();
+        let mut ops = (0..ints.len()).map(|_| None).collect_vec();
+        let mut cells = vec![0; ints.len()];
+        for line in cfg::analyse(&ints).blocks().flat_map(|block| &block.lines) {
+            let addr = line.addr();
+            let decoded = match Decoded::at(mem, addr) {
+                Ok(decoded) => decoded,
+                Err(_) => continue,
+            };
+            // instructions that overlap, or run off the end of the dense words,
+            // are left to the interpreter
+            let end = addr + decoded.insn.arity() + 1;
+            match cells.get(addr..end) {
+                Some(cells) if cells.iter().all(|&cell| cell == 0) => (),
+                _ => continue,
+            }
+            for (offset, cell) in (1..).zip(&mut cells[addr..end]) {
+                *cell = offset;
+            }
+            ops[addr] = op(addr, decoded);
+        }
+        (Code { ops }, cells)
+    }
+
+    // Runs compiled instructions until one of them stops the VM, or it comes to
+    // one that needs the interpreter. `at` keeps up with the instruction being
+    // run so errors can point at it.
+    pub(super) fn run(&self, vm: &mut VM<W>, at: &mut usize) -> VMResult<Option<State<W>>> {
+        if vm.tracer.is_some()
+            || vm.undo.is_some()
+            || vm.resume.is_some()
+            || !vm.breakpoints.is_empty()
+            || !vm.watchpoints.is_empty()
+        {
+            return vm.advance();
+        }
+        loop {
+            *at = vm.insn;
+            let op = match self.ops.get(vm.insn) {
+                Some(Some(op)) if vm.fuel != Some(0) && !vm.mem.is_stale(vm.insn) => op,
+                _ => return vm.advance(),
+            };
+            if let Some(state) = op(vm)? {
+                return Ok(Some(state));
+            }
+        }
+    }
+}
+
+fn op<W: Word>(addr: usize, decoded: Decoded<W>) -> Option<Op<W>> {
+    let Decoded {
+        insn,
+        modes,
+        params,
+    } = decoded;
+    let next = addr + insn.arity() + 1;
+    let load = |i: usize| load(modes[i], params[i].clone());
+    let store = |i: usize| store(modes[i], params[i].clone());
+    Some(match insn {
+        Insn::Add | Insn::Mul | Insn::LessThan | Insn::Equals => {
+            let (a, b, c) = (load(0), load(1), store(2));
+            let f: fn(&W, &W) -> Option<W> = match insn {
+                Insn::Add => |a, b| a.checked_add(b),
+                Insn::Mul => |a, b| a.checked_mul(b),
+                Insn::LessThan => |a, b| Some(flag(a < b)),
+                _ => |a, b| Some(flag(a == b)),
+            };
+            Box::new(move |vm| {
+                vm.insn = next;
+                let (a, b) = (a(vm)?, b(vm)?);
+                c(vm, f(&a, &b).ok_or(Error::Overflow)?)?;
+                vm.count(addr, insn);
+                Ok(None)
+            })
+        }
+        Insn::Input => {
+            let a = store(0);
+            Box::new(move |vm| {
+                if vm.inbuf.is_empty() {
+                    return Ok(Some(State::AwaitingInput));
+                }
+                vm.insn = next;
+                let input = vm.inbuf.pop_front().unwrap();
+                a(vm, input)?;
+                vm.count(addr, insn);
+                Ok(None)
+            })
+        }
+        Insn::Output => {
+            let a = load(0);
+            Box::new(move |vm| {
+                vm.insn = next;
+                let out = a(vm)?;
+                vm.count(addr, insn);
+                Ok(Some(State::Outputting(out)))
+            })
+        }
+        Insn::JumpIfTrue | Insn::JumpIfFalse => {
+            let (a, b) = (load(0), load(1));
+            let when = insn == Insn::JumpIfTrue;
+            Box::new(move |vm| {
+                vm.insn = next;
+                let (cond, to) = (a(vm)?, b(vm)?);
+                if cond.is_zero() != when {
+                    vm.jump(&to)?;
+                }
+                vm.count(addr, insn);
+                Ok(None)
+            })
+        }
+        Insn::SetBase => {
+            let a = load(0);
+            Box::new(move |vm| {
+                vm.insn = next;
+                let offset = a(vm)?.to_int()?;
+                vm.relbase = vm.relbase.checked_add(offset).ok_or(Error::Overflow)?;
+                vm.count(addr, insn);
+                Ok(None)
+            })
+        }
+        Insn::End => Box::new(move |vm| {
+            vm.halted = true;
+            vm.count(addr, insn);
+            Ok(Some(State::Finished))
+        }),
+        Insn::Ext(_) => return None,
+    })
+}
+
+fn load<W: Word>(mode: Mode, param: W) -> Load<W> {
+    match (mode, param.to_int()) {
+        (Mode::Immediate, _) => Box::new(move |_| Ok(param.clone())),
+        (_, Err(e)) => Box::new(move |_| Err(e)),
+        (Mode::Position, Ok(addr)) if addr < 0 => {
+            Box::new(move |_| Err(Error::MemoryOutOfBounds(addr)))
+        }
+        (Mode::Position, Ok(addr)) => Box::new(move |vm| {
+            vm.touch(addr as usize);
+            vm.mem.load(addr as usize)
+        }),
+        (Mode::Relative, Ok(offset)) => Box::new(move |vm| {
+            let addr = relative(vm, offset)?;
+            vm.mem.load(addr)
+        }),
+    }
+}
+
+fn store<W: Word>(mode: Mode, param: W) -> Store<W> {
+    match (mode, param.to_int()) {
+        (Mode::Immediate, _) => Box::new(move |_, _| Err(Error::UnsupportedSet(mode))),
+        (_, Err(e)) => Box::new(move |_, _| Err(e)),
+        (Mode::Position, Ok(addr)) if addr < 0 => {
+            Box::new(move |_, _| Err(Error::MemoryOutOfBounds(addr)))
+        }
+        (Mode::Position, Ok(addr)) => Box::new(move |vm, val| {
+            vm.touch(addr as usize);
+            vm.mem.store(addr as usize, val)
+        }),
+        (Mode::Relative, Ok(offset)) => Box::new(move |vm, val| {
+            let addr = relative(vm, offset)?;
+            vm.mem.store(addr, val)
+        }),
+    }
+}
+
+fn flag<W: Word>(b: bool) -> W {
+    if b {
+        W::one()
+    } else {
+        W::zero()
+    }
+}
+
+fn relative<W: Word>(vm: &VM<W>, offset: Int) -> VMResult<usize> {
+    let addr = vm.relbase.checked_add(offset).ok_or(Error::Overflow)?;
+    if addr < 0 {
+        return Err(Error::MemoryOutOfBounds(addr));
+    }
+    vm.touch(addr as usize);
+    Ok(addr as usize)
+}
@@ -0,0 +1,349 @@
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+
+use itertools::Itertools;
+
+use crate::intcode::cfg;
+use crate::intcode::disasm::Line;
+use crate::intcode::transcript::Transcript;
+use crate::intcode::{Access, ExecError, Insn, Int, Program, State, VM};
+use crate::io::{self, stdin, stdout, BufRead, Write};
+
+const HELP: &str = "\
+run                      run until a breakpoint, watchpoint, input or the end
+step [n]                 execute n instructions (default 1)
+back [n]                 undo n instructions (default 1)
+lastwrite <addr>         go back to just before addr was last written
+lastout                  go back to just before the last output
+break [addr]             set a breakpoint, or list breakpoints and watchpoints
+watch <addr> [r|w|rw]    stop before memory at addr is accessed (default w)
+delete <addr>            remove a breakpoint or watchpoint
+mem <addr> [n]           show n memory cells (default 1)
+regs                     show the instruction pointer and relative base
+input <values...>        queue input, or a line of text in ascii mode
+ascii                    toggle ascii mode for input and output
+dump [addr] [n]          disassemble n instructions (default ip, 10)
+cfg <file>               write the control-flow graph of memory as Graphviz DOT
+fuel [n]                 stop after n more instructions, or never
+stats                    show instruction counts and memory touched
+reset                    reload the program
+save <file>              write a snapshot of the VM to file
+load <file>              restore the VM from a snapshot
+replay <file>            reset and check the program against a transcript
+quit";
+
+struct Debugger {
+    program: Program,
+    vm: VM,
+    ascii: bool,
+}
+
+// How many instructions the debugger can step back through.
+const UNDO_LIMIT: usize = 1 << 20;
+
+pub fn debugger() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            io::println!("Usage: intdbg <program>");
+            return;
+        }
+    };
+    let program = match Program::from_file(&path) {
+        Ok(program) => program,
+        Err(e) => {
+            io::println!("{}: {}", path, e);
+            return;
+        }
+    };
+    let mut dbg = Debugger {
+        vm: VM::of(&program).with_undo_log(UNDO_LIMIT).with_counts(),
+        program,
+        ascii: false,
+    };
+
+    let stdin = stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        io::print!("(intdbg) ");
+        stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(line) => line.unwrap(),
+            None => break,
+        };
+        let (cmd, rest) = match line.trim().find(' ') {
+            Some(i) => (&line.trim()[..i], line.trim()[i..].trim()),
+            None => (line.trim(), ""),
+        };
+        let args = rest.split_whitespace().collect_vec();
+        match cmd {
+            "" => (),
+            "run" | "r" => dbg.run(),
+            "step" | "s" => dbg.step(arg(&args, 0).unwrap_or(1)),
+            "back" => dbg.back(arg(&args, 0).unwrap_or(1)),
+            "lastwrite" => match arg(&args, 0) {
+                Some(addr) => match dbg.vm.rewind_to_write(addr) {
+                    Some(at) => dbg.dump(at, 1),
+                    None => io::println!("No write to {} in the undo log", addr),
+                },
+                None => io::println!("Usage: lastwrite <addr>"),
+            },
+            "lastout" => match dbg.vm.rewind_to_output() {
+                Some(i) => {
+                    io::println!("Before output {}", i);
+                    dbg.dump(dbg.vm.ip(), 1);
+                }
+                None => io::println!("No output in the undo log"),
+            },
+            "break" | "b" => match arg(&args, 0) {
+                Some(addr) => dbg.vm.add_breakpoint(addr),
+                None => dbg.list_breakpoints(),
+            },
+            "watch" | "w" => match (arg(&args, 0), args.get(1).copied()) {
+                (Some(addr), None) | (Some(addr), Some("w")) => {
+                    dbg.vm.add_watchpoint(addr, Access::Write)
+                }
+                (Some(addr), Some("r")) => dbg.vm.add_watchpoint(addr, Access::Read),
+                (Some(addr), Some("rw")) => dbg.vm.add_watchpoint(addr, Access::ReadWrite),
+                _ => io::println!("Usage: watch <addr> [r|w|rw]"),
+            },
+            "delete" | "d" => match arg(&args, 0) {
+                Some(addr) => {
+                    if !dbg.vm.remove_breakpoint(addr) && !dbg.vm.remove_watchpoint(addr) {
+                        io::println!("Nothing set at {}", addr);
+                    }
+                }
+                None => io::println!("Usage: delete <addr>"),
+            },
+            "mem" | "m" => match arg(&args, 0) {
+                Some(addr) => dbg.mem(addr, arg(&args, 1).unwrap_or(1)),
+                None => io::println!("Usage: mem <addr> [n]"),
+            },
+            "regs" => io::println!("ip={} rb={}", dbg.vm.ip(), dbg.vm.relbase()),
+            "input" | "i" => dbg.input(rest),
+            "ascii" | "a" => {
+                dbg.ascii = !dbg.ascii;
+                io::println!("ascii mode {}", if dbg.ascii { "on" } else { "off" });
+            }
+            "dump" => {
+                let addr = arg(&args, 0).unwrap_or_else(|| dbg.vm.ip());
+                dbg.dump(addr, arg(&args, 1).unwrap_or(10));
+            }
+            "fuel" => {
+                dbg.vm.set_fuel(arg(&args, 0));
+                match dbg.vm.fuel() {
+                    Some(fuel) => io::println!("fuel {}", fuel),
+                    None => io::println!("unlimited fuel"),
+                }
+            }
+            "stats" => dbg.stats(),
+            "reset" => dbg.reset(),
+            "save" if !rest.is_empty() => dbg.save(rest),
+            "load" if !rest.is_empty() => dbg.load(rest),
+            "replay" if !rest.is_empty() => dbg.replay(rest),
+            "cfg" if !rest.is_empty() => dbg.cfg(rest),
+            "save" | "load" | "replay" | "cfg" => io::println!("Usage: {} <file>", cmd),
+            "help" | "h" => io::println!("{}", HELP),
+            "quit" | "q" => break,
+            _ => io::println!("Unknown command {:?}, try help", cmd),
+        }
+    }
+}
+
+fn arg(args: &[&str], i: usize) -> Option<usize> {
+    args.get(i).and_then(|s| s.parse().ok())
+}
+
+impl Debugger {
+    fn run(&mut self) {
+        loop {
+            let state = self.vm.next_state();
+            if !self.report(state) {
+                return;
+            }
+        }
+    }
+
+    fn step(&mut self, n: usize) {
+        for _ in 0..n {
+            self.dump(self.vm.ip(), 1);
+            match VM::step(&mut self.vm) {
+                Ok(None) => (),
+                Ok(Some(state)) => {
+                    if !self.report(Ok(state)) {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    self.report(Err(e));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn back(&mut self, n: usize) {
+        for _ in 0..n {
+            if !self.vm.step_back() {
+                io::println!("Can't go back any further");
+                break;
+            }
+        }
+        self.dump(self.vm.ip(), 1);
+    }
+
+    // Returns whether execution can carry on.
+    fn report(&self, state: Result<State, ExecError>) -> bool {
+        match state {
+            Ok(State::Outputting(i)) => {
+                self.output(i);
+                return true;
+            }
+            Ok(State::AwaitingInput) => io::println!("Awaiting input at {}", self.vm.ip()),
+            Ok(State::Finished) => io::println!("Finished at {}", self.vm.ip()),
+            Ok(State::OutOfFuel) => io::println!("Out of fuel at {}", self.vm.ip()),
+            Ok(State::Breakpoint(addr)) => {
+                io::println!("Breakpoint at {}", addr);
+                self.dump(addr, 1);
+            }
+            Ok(State::Watch(addr, access)) => {
+                io::println!("Watchpoint: {:?} of {}", access, addr);
+                self.dump(self.vm.ip(), 1);
+            }
+            Err(e) => io::println!("Error: {}", e),
+        }
+        false
+    }
+
+    fn reset(&mut self) {
+        self.replace(VM::of(&self.program));
+    }
+
+    fn save(&self, path: &str) {
+        if let Err(e) = File::create(path).and_then(|mut file| self.vm.save(&mut file)) {
+            io::println!("Couldn't save to {}: {}", path, e);
+        }
+    }
+
+    fn load(&mut self, path: &str) {
+        match File::open(path) {
+            Ok(file) => match VM::load(BufReader::new(file)) {
+                Ok(vm) => self.replace(vm),
+                Err(e) => io::println!("Couldn't load {}: {:?}", path, e),
+            },
+            Err(e) => io::println!("Couldn't open {}: {}", path, e),
+        }
+    }
+
+    fn cfg(&self, path: &str) {
+        let cfg = cfg::analyse(self.vm.mem.words());
+        if let Err(e) = File::create(path).and_then(|mut file| cfg.write_dot(&mut file)) {
+            return io::println!("Couldn't write {}: {}", path, e);
+        }
+        io::println!(
+            "{} blocks in {} functions",
+            cfg.blocks().count(),
+            cfg.functions().count()
+        );
+        for (addr, cell) in cfg.self_modifying() {
+            io::println!("{} writes to code at {}", addr, cell);
+        }
+    }
+
+    fn replay(&mut self, path: &str) {
+        let transcript = match File::open(path) {
+            Ok(file) => match Transcript::load(BufReader::new(file)) {
+                Ok(transcript) => transcript,
+                Err(e) => return io::println!("Couldn't load {}: {:?}", path, e),
+            },
+            Err(e) => return io::println!("Couldn't open {}: {}", path, e),
+        };
+        self.reset();
+        match self.vm.replay(&transcript) {
+            Ok(()) => io::println!("Replayed {} events", transcript.entries().len()),
+            Err(e) => io::println!("Replay failed: {}", e),
+        }
+    }
+
+    // Swaps in a new VM, keeping the breakpoints and watchpoints.
+    fn replace(&mut self, vm: VM) {
+        let mut vm = vm.with_counts();
+        vm.set_undo_log(Some(UNDO_LIMIT));
+        self.vm
+            .breakpoints()
+            .for_each(|addr| vm.add_breakpoint(addr));
+        self.vm
+            .watchpoints()
+            .for_each(|(addr, access)| vm.add_watchpoint(addr, access));
+        self.vm = vm;
+    }
+
+    fn output(&self, i: Int) {
+        if self.ascii && (0..128).contains(&i) {
+            io::print!("{}", i as u8 as char);
+        } else {
+            io::println!("Output: {}", i);
+        }
+    }
+
+    fn input(&mut self, rest: &str) {
+        if self.ascii {
+            self.vm.input_ascii(rest);
+            self.vm.input('\n' as Int);
+            return;
+        }
+        match rest
+            .split_whitespace()
+            .map(|s| s.parse::<Int>())
+            .collect::<Result<Vec<Int>, _>>()
+        {
+            Ok(values) => values.into_iter().for_each(|i| self.vm.input(i)),
+            Err(e) => io::println!("Bad input: {}", e),
+        }
+    }
+
+    fn stats(&self) {
+        io::println!("executed {}", self.vm.executed());
+        for (insn, n) in self.vm.histogram() {
+            let name = match insn {
+                Insn::Ext(op) => format!("EXT {}", op),
+                _ => insn.mnemonic().to_owned(),
+            };
+            io::println!("{:>14} {}", name, n);
+        }
+        io::println!("memory touched {}", self.vm.memory_touched());
+    }
+
+    fn list_breakpoints(&self) {
+        for addr in self.vm.breakpoints().sorted() {
+            io::println!("break {}", addr);
+        }
+        for (addr, access) in self.vm.watchpoints().sorted_by_key(|(addr, _)| *addr) {
+            io::println!("watch {} {:?}", addr, access);
+        }
+    }
+
+    fn mem(&self, addr: usize, n: usize) {
+        for a in (0..n).map_while(|i| addr.checked_add(i)) {
+            io::println!("{:>6}: {}", a, self.vm.mem[a]);
+        }
+    }
+
+    fn dump(&self, mut addr: usize, n: usize) {
+        for _ in 0..n {
+            let line = match Line::decode(addr, |a| self.vm.mem.get(a)) {
+                Some(line) => line,
+                None => return,
+            };
+            let mark = if addr == self.vm.ip() { '>' } else { ' ' };
+            let bp = if self.vm.breakpoints().any(|b| b == addr) {
+                '*'
+            } else {
+                ' '
+            };
+            io::println!("{}{}{}", mark, bp, line);
+            addr += line.size();
+        }
+    }
+}
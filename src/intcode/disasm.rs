@@ -0,0 +1,142 @@
+use std::fmt::{self, Display, Formatter};
+
+use itertools::Itertools;
+
+use crate::intcode::word::Word;
+use crate::intcode::{Insn, Int, Mode};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Operand<W = Int> {
+    pub mode: Mode,
+    pub value: W,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Line {
+    Insn {
+        addr: usize,
+        word: Int,
+        insn: Insn,
+        operands: Vec<Operand>,
+    },
+    Data {
+        addr: usize,
+        value: Int,
+    },
+}
+
+pub struct Disassembly<'a> {
+    mem: &'a [Int],
+    addr: usize,
+}
+
+pub fn disassemble(mem: &[Int]) -> Disassembly<'_> {
+    Disassembly { mem, addr: 0 }
+}
+
+impl Line {
+    pub fn at(mem: &[Int], addr: usize) -> Option<Line> {
+        Line::decode(addr, |a| mem.get(a).copied())
+    }
+
+    pub fn decode<F: Fn(usize) -> Option<Int>>(addr: usize, word_at: F) -> Option<Line> {
+        let word = word_at(addr)?;
+        let data = Line::Data { addr, value: word };
+        let (insn, modes) = match Insn::decode(word) {
+            Ok(decoded) => decoded,
+            Err(_) => return Some(data),
+        };
+        let arity = insn.arity();
+        if insn.writes() && modes[arity - 1] == Mode::Immediate {
+            return Some(data);
+        }
+        let operands = match modes
+            .iter()
+            .zip(addr + 1..=addr + arity)
+            .map(|(&mode, a)| {
+                Some(Operand {
+                    mode,
+                    value: word_at(a)?,
+                })
+            })
+            .collect()
+        {
+            Some(operands) => operands,
+            None => return Some(data),
+        };
+        Some(Line::Insn {
+            addr,
+            word,
+            insn,
+            operands,
+        })
+    }
+
+    pub fn addr(&self) -> usize {
+        match self {
+            Line::Insn { addr, .. } | Line::Data { addr, .. } => *addr,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Line::Insn { operands, .. } => operands.len() + 1,
+            Line::Data { .. } => 1,
+        }
+    }
+}
+
+impl Iterator for Disassembly<'_> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        let line = Line::at(self.mem, self.addr)?;
+        self.addr += line.size();
+        Some(line)
+    }
+}
+
+impl<W: Word> Display for Operand<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < W::zero() => write!(f, "rb{}", self.value),
+            Mode::Relative => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Insn {
+                addr,
+                word,
+                insn,
+                operands,
+            } => {
+                let raw = Some(*word)
+                    .into_iter()
+                    .chain(operands.iter().map(|o| o.value))
+                    .join(",");
+                let text = format!(
+                    "{:>6}: {:<24} {:<13} {}",
+                    addr,
+                    raw,
+                    insn.mnemonic(),
+                    operands.iter().join(", ")
+                );
+                f.write_str(text.trim_end())
+            }
+            Line::Data { addr, value } => {
+                write!(f, "{:>6}: {:<24} {:<13} {}", addr, value, "DATA", value)?;
+                match *value {
+                    10 => write!(f, " ; '\\n'"),
+                    32..=126 => write!(f, " ; '{}'", *value as u8 as char),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
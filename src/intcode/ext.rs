@@ -0,0 +1,108 @@
+use std::sync::{Arc, Mutex};
+
+use crate::intcode::word::Word;
+use crate::intcode::{Error, Int, VMResult};
+use crate::io;
+
+// An instruction outside the standard set, run by the VM when it comes across
+// an opcode registered with `VM::with_extension`. Its operands are decoded
+// like any other instruction's, modes included: the first `reads` are read and
+// handed to the handler, and if it `writes`, what the handler returns is
+// written to the last one.
+//
+// The handler is shared between clones of the VM, so it can also be a way out
+// to host code, with whatever state it closes over.
+//
+// Only the VM knows its extensions. Disassembly, `cfg` and compiled code work
+// from memory alone, so to them an extension's opcode is data: the listing
+// shows it as a word, the control-flow graph ends its block there, and the
+// compiled code hands it, and whatever follows, to the interpreter.
+#[derive(Clone)]
+pub struct Extension<W = Int> {
+    reads: usize,
+    writes: bool,
+    handler: Handler<W>,
+}
+
+type Handler<W> = Arc<Mutex<dyn FnMut(&[W]) -> VMResult<Option<W>> + Send>>;
+
+impl<W: Word> Extension<W> {
+    pub fn new<F>(reads: usize, writes: bool, handler: F) -> Self
+    where
+        F: FnMut(&[W]) -> VMResult<Option<W>> + Send + 'static,
+    {
+        Extension {
+            reads,
+            writes,
+            handler: Arc::new(Mutex::new(handler)),
+        }
+    }
+
+    pub fn reads(&self) -> usize {
+        self.reads
+    }
+
+    pub fn writes(&self) -> bool {
+        self.writes
+    }
+
+    pub fn arity(&self) -> usize {
+        self.reads + self.writes as usize
+    }
+
+    pub(super) fn handler(&self) -> Handler<W> {
+        self.handler.clone()
+    }
+
+    // Rounds towards zero, like Rust.
+    pub fn div() -> Self {
+        Extension::new(2, true, |args: &[W]| {
+            if args[1].is_zero() {
+                return Err(Error::DivisionByZero);
+            }
+            args[0]
+                .checked_div(&args[1])
+                .ok_or(Error::Overflow)
+                .map(Some)
+        })
+    }
+
+    pub fn rem() -> Self {
+        Extension::new(2, true, |args: &[W]| {
+            if args[1].is_zero() {
+                return Err(Error::DivisionByZero);
+            }
+            // the remainder of the one division that overflows is still zero
+            match args[0].checked_div(&args[1]) {
+                Some(_) => Ok(Some(args[0].clone() % args[1].clone())),
+                None => Ok(Some(W::zero())),
+            }
+        })
+    }
+
+    pub fn and() -> Self {
+        Extension::new(2, true, |args: &[W]| {
+            Ok(Some(args[0].clone() & args[1].clone()))
+        })
+    }
+
+    pub fn or() -> Self {
+        Extension::new(2, true, |args: &[W]| {
+            Ok(Some(args[0].clone() | args[1].clone()))
+        })
+    }
+
+    pub fn xor() -> Self {
+        Extension::new(2, true, |args: &[W]| {
+            Ok(Some(args[0].clone() ^ args[1].clone()))
+        })
+    }
+
+    // Prints its operand, for debugging.
+    pub fn print() -> Self {
+        Extension::new(1, false, |args: &[W]| {
+            io::println!("{}", args[0]);
+            Ok(None)
+        })
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+use crate::intcode::word::Word;
+use crate::intcode::{Decoded, Error, Int, VMResult};
+
+const PAGE_BITS: usize = 12;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+// Dense memory allocates a cell for whatever address a program touches. A
+// snapshot is only trusted to ask it for this many without a limit.
+const DENSE_LIMIT: usize = 1 << 24;
+
+type DecodeCache<W> = Arc<Vec<Option<(W, Decoded<W>)>>>;
+
+// Memory always starts with a dense run of words holding the program. Dense
+// memory grows that run to fit any write, while paged memory puts everything
+// past it into fixed size pages, allocated on first write.
+#[derive(Clone)]
+pub struct Memory<W = Int> {
+    words: Vec<W>,
+    pages: Option<HashMap<usize, Box<[W]>>>,
+    len: usize,
+    limit: Option<usize>,
+    zero: W,
+    cache: Option<DecodeCache<W>>,
+    code: Option<Arc<Vec<u8>>>,
+    stale: Vec<bool>,
+}
+
+impl<W: Word> Memory<W> {
+    pub fn dense(words: Vec<W>) -> Self {
+        Memory {
+            len: words.len(),
+            words,
+            pages: None,
+            limit: None,
+            zero: W::zero(),
+            cache: None,
+            code: None,
+            stale: Vec::new(),
+        }
+    }
+
+    pub fn paged(words: Vec<W>) -> Self {
+        Memory {
+            pages: Some(HashMap::new()),
+            ..Memory::dense(words)
+        }
+    }
+
+    pub fn into_paged(mut self) -> Self {
+        if self.pages.is_none() {
+            self.pages = Some(HashMap::new());
+        }
+        self
+    }
+
+    // Decodes every word of the program up front as if it were the start of
+    // an instruction. The cache is shared between clones and never written
+    // to; an entry is only used while the words it was decoded from are
+    // unchanged, so self-modifying code falls back to decoding as it goes.
+    pub fn with_decode_cache(mut self) -> Self {
+        let entries = (0..self.words.len())
+            .map(|addr| {
+                let decoded = Decoded::at(&self, addr).ok()?;
+                Some((self.words[addr].clone(), decoded))
+            })
+            .collect();
+        self.cache = Some(Arc::new(entries));
+        self
+    }
+
+    // Keeps watch over the cells of compiled instructions, each marked with one
+    // more than its offset from the start of its instruction, or 0 if it isn't
+    // compiled. Writing to one makes its instruction stale.
+    pub(super) fn with_code_guard(mut self, cells: Vec<u8>) -> Self {
+        self.stale = vec![false; cells.len()];
+        self.code = Some(Arc::new(cells));
+        self
+    }
+
+    // Whether the instruction at `addr` can't be run compiled, which includes
+    // when there's no compiled code at all.
+    pub(super) fn is_stale(&self, addr: usize) -> bool {
+        self.stale.get(addr).copied().unwrap_or(true)
+    }
+
+    // Makes every address from `limit` on out of bounds. Without one, memory
+    // takes any address a program uses.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    // Makes room for `len` cells if it can. They have to be under the limit,
+    // dense memory without one won't take more than `DENSE_LIMIT`, and dense
+    // memory has to be able to allocate them.
+    pub(super) fn make_room(&mut self, len: usize) -> bool {
+        if matches!(self.limit, Some(limit) if len > limit) {
+            return false;
+        }
+        if self.pages.is_some() {
+            return true;
+        }
+        (self.limit.is_some() || len <= DENSE_LIMIT)
+            && self
+                .words
+                .try_reserve(len.saturating_sub(self.words.len()))
+                .is_ok()
+    }
+
+    pub(super) fn is_cached(&self) -> bool {
+        self.cache.is_some()
+    }
+
+    pub fn is_paged(&self) -> bool {
+        self.pages.is_some()
+    }
+
+    // One past the highest address that has been written to.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, addr: usize) -> Option<W> {
+        if addr < self.len {
+            Some(self[addr].clone())
+        } else {
+            None
+        }
+    }
+
+    // Every word up to `len`, gaps and all, so for paged memory written far out
+    // this is as long as the whole span. `words` and `cells` aren't.
+    pub fn iter(&self) -> impl Iterator<Item = W> + '_ {
+        (0..self.len).map(move |addr| self[addr].clone())
+    }
+
+    pub fn to_vec(&self) -> Vec<W> {
+        self.iter().collect()
+    }
+
+    // The dense run at the start of memory: the program, and for dense memory
+    // everything written since. It's what gets analysed as code, and stays the
+    // size of the program in paged memory however far out that's written to.
+    #[inline]
+    pub fn words(&self) -> &[W] {
+        &self.words
+    }
+
+    // Every cell that has been allocated, in address order, without walking
+    // the gaps between pages.
+    pub(super) fn cells(&self) -> impl Iterator<Item = (usize, &W)> + '_ {
+        let base = self.words.len();
+        let mut pages = self
+            .pages
+            .iter()
+            .flat_map(|pages| pages.iter())
+            .collect::<Vec<_>>();
+        pages.sort_by_key(|(&page, _)| page);
+        self.words.iter().enumerate().chain(
+            pages
+                .into_iter()
+                .flat_map(move |(&page, cells)| {
+                    cells
+                        .iter()
+                        .enumerate()
+                        .map(move |(i, w)| (base + (page << PAGE_BITS) + i, w))
+                })
+                .take_while(move |&(addr, _)| addr < self.len),
+        )
+    }
+
+    // Extends the memory as if `len - 1` had been written to.
+    pub(super) fn grow(&mut self, len: usize) {
+        if len > self.len {
+            self.cell_mut(len - 1);
+        }
+    }
+
+    // Forgets everything from `len` on, as if it had never been written to.
+    // Forgets everything from `len` on, which has to have been zeroed, freeing
+    // the pages that start past it.
+    pub(super) fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.len = len;
+            let dense = self.words.len();
+            match &mut self.pages {
+                None => self.words.truncate(len),
+                Some(pages) => pages.retain(|&page, _| dense + (page << PAGE_BITS) < len),
+            }
+        }
+    }
+
+    pub(super) fn decoded(&self, addr: usize) -> Option<Decoded<W>> {
+        let (word, decoded) = self.cache.as_ref()?.get(addr)?.as_ref()?;
+        let arity = decoded.insn.arity();
+        let current = self.words.get(addr..=addr + arity)?;
+        if current[0] == *word && current[1..] == decoded.params[..arity] {
+            Some(decoded.clone())
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub(super) fn load(&self, addr: usize) -> VMResult<W> {
+        if matches!(self.limit, Some(limit) if addr >= limit) {
+            return Err(Error::MemoryOutOfBounds(addr as Int));
+        }
+        Ok(self[addr].clone())
+    }
+
+    #[inline]
+    pub(super) fn store(&mut self, addr: usize, val: W) -> VMResult<()> {
+        if matches!(self.limit, Some(limit) if addr >= limit) {
+            return Err(Error::MemoryOutOfBounds(addr as Int));
+        }
+        self[addr] = val;
+        Ok(())
+    }
+
+    fn cell_mut(&mut self, addr: usize) -> &mut W {
+        if addr >= self.len {
+            self.len = addr + 1;
+        }
+        match &mut self.pages {
+            None => {
+                self.words.resize(addr + 1, W::zero());
+                &mut self.words[addr]
+            }
+            Some(pages) => {
+                let offset = addr - self.words.len();
+                &mut pages
+                    .entry(offset >> PAGE_BITS)
+                    .or_insert_with(|| vec![W::zero(); PAGE_SIZE].into_boxed_slice())
+                    [offset & (PAGE_SIZE - 1)]
+            }
+        }
+    }
+}
+
+impl<W: Word> Index<usize> for Memory<W> {
+    type Output = W;
+
+    #[inline]
+    fn index(&self, addr: usize) -> &W {
+        if addr < self.words.len() {
+            return &self.words[addr];
+        }
+        let offset = addr - self.words.len();
+        self.pages
+            .as_ref()
+            .and_then(|pages| pages.get(&(offset >> PAGE_BITS)))
+            .map_or(&self.zero, |page| &page[offset & (PAGE_SIZE - 1)])
+    }
+}
+
+impl<W: Word> IndexMut<usize> for Memory<W> {
+    #[inline]
+    fn index_mut(&mut self, addr: usize) -> &mut W {
+        if addr < self.words.len() {
+            match self.code.as_ref().and_then(|code| code.get(addr)) {
+                Some(&offset) if offset > 0 => self.stale[addr + 1 - offset as usize] = true,
+                _ => (),
+            }
+            return &mut self.words[addr];
+        }
+        self.cell_mut(addr)
+    }
+}
@@ -0,0 +1,1223 @@
+use crate::io::{self, stdin, BufRead};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::num::ParseIntError;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+use itertools::Itertools;
+
+use crate::intcode::compile::Code;
+use crate::intcode::ext::Extension;
+use crate::intcode::memory::Memory;
+use crate::intcode::trace::{Step, Tracer};
+use crate::intcode::undo::UndoLog;
+use crate::intcode::word::Word;
+
+pub mod ascii;
+pub mod asm;
+pub mod cfg;
+pub mod compile;
+pub mod debugger;
+pub mod disasm;
+pub mod ext;
+pub mod memory;
+pub mod network;
+pub mod pipeline;
+pub mod profile;
+pub mod snapshot;
+pub mod stream;
+pub mod symbolic;
+pub mod trace;
+pub mod transcript;
+pub mod undo;
+pub mod word;
+
+pub type Int = i64;
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    instructions: Vec<Int>,
+}
+
+#[derive(Debug)]
+pub enum ParseProgramError {
+    NotInteger(Position, String, ParseIntError),
+    IOError(io::Error),
+}
+
+// Where a word is in the program, and in its source, counting lines and
+// columns from 1.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Position {
+    pub index: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Programs are comma separated, and can be split over any number of lines with
+// whitespace around the words, a trailing comma and `#` comments:
+//
+//   109,1,   # set up the stack
+//   204,-1,
+//   99
+impl Program {
+    // Reads lines up to the end of the program, which is the first one that
+    // has words on it and doesn't end with a comma, leaving the rest of stdin
+    // for the program's input.
+    pub fn from_stdin() -> Result<Program, ParseProgramError> {
+        let stdin = stdin();
+        let mut parser = Parser::default();
+        for line in stdin.lock().lines() {
+            let line = line.map_err(ParseProgramError::IOError)?;
+            if !parser.line(&line)? {
+                break;
+            }
+        }
+        Ok(parser.finish())
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Program, ParseProgramError> {
+        let mut parser = Parser::default();
+        for line in reader.lines() {
+            parser.line(&line.map_err(ParseProgramError::IOError)?)?;
+        }
+        Ok(parser.finish())
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Program, ParseProgramError> {
+        let file = File::open(path).map_err(ParseProgramError::IOError)?;
+        Program::from_reader(BufReader::new(file))
+    }
+
+    pub fn assemble(source: &str) -> Result<Program, asm::AssembleError> {
+        asm::assemble(source)
+    }
+
+    pub fn disassemble(&self) -> disasm::Disassembly<'_> {
+        disasm::disassemble(&self.instructions)
+    }
+
+    pub fn cfg(&self) -> cfg::Cfg {
+        cfg::analyse(&self.instructions)
+    }
+
+    pub fn into_fn(self) -> impl Fn(Vec<Int>) -> Vec<Int> {
+        let template = VM::compiled(&self);
+        move |input| {
+            let mut vm = template.clone();
+            for i in input {
+                vm.input(i);
+            }
+            let mut ret = Vec::new();
+            loop {
+                match vm.next_state() {
+                    Ok(State::Outputting(i)) => ret.push(i),
+                    Ok(State::Finished) => return ret,
+                    Ok(s) => panic!("Unexpected state: {:?}", s),
+                    Err(e) => panic!("{:?}", e),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseProgramError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::default();
+        for line in s.lines() {
+            parser.line(line)?;
+        }
+        Ok(parser.finish())
+    }
+}
+
+#[derive(Default)]
+struct Parser {
+    words: Vec<Int>,
+    line: usize,
+}
+
+impl Parser {
+    // Parses the next line, and says whether the program carries on past it.
+    fn line(&mut self, line: &str) -> Result<bool, ParseProgramError> {
+        self.line += 1;
+        let text = line.split('#').next().unwrap();
+        if text.trim().is_empty() {
+            return Ok(true);
+        }
+        let mut column = 1;
+        let mut pieces = text.split(',').peekable();
+        while let Some(piece) = pieces.next() {
+            let word = piece.trim();
+            let start = column + piece.chars().count() - piece.trim_start().chars().count();
+            column += piece.chars().count() + 1;
+            if word.is_empty() && pieces.peek().is_none() {
+                // a trailing comma
+                return Ok(true);
+            }
+            let position = Position {
+                index: self.words.len(),
+                line: self.line,
+                column: start,
+            };
+            let word = word
+                .parse()
+                .map_err(|e| ParseProgramError::NotInteger(position, word.to_owned(), e))?;
+            self.words.push(word);
+        }
+        Ok(false)
+    }
+
+    fn finish(self) -> Program {
+        Program {
+            instructions: self.words,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum State<W = Int> {
+    AwaitingInput,
+    Outputting(W),
+    Finished,
+    Breakpoint(usize),
+    Watch(usize, Access),
+    OutOfFuel,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Error {
+    UnrecognisedMode(u8),
+    UnrecognisedOpcode(u8),
+    UnsupportedOperation,
+    MemoryOutOfBounds(Int),
+    UnterminatedProgram(usize),
+    UnsupportedSet(Mode),
+    Overflow,
+    DivisionByZero,
+}
+
+pub struct ExecError<W = Int> {
+    mem: Vec<W>,
+    start: usize,
+    len: usize,
+    insn: usize,
+    relbase: Int,
+    history: Vec<usize>,
+    error: Error,
+}
+
+// How many words either side of the faulting instruction an ExecError keeps,
+// and how many of those its Display shows.
+const ERROR_WINDOW: usize = 32;
+const DISPLAY_WINDOW: usize = 6;
+
+// How many of the most recently executed instructions the VM remembers.
+const HISTORY: usize = 16;
+
+// One slot per opcode in the VM's instruction histogram.
+const OPCODES: usize = 100;
+
+type VMResult<T> = Result<T, Error>;
+type ExecResult<T, W> = Result<T, ExecError<W>>;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Insn {
+    Add,
+    Mul,
+    Input,
+    Output,
+    End,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    SetBase,
+    // An opcode from the VM's extensions, which also know its arity.
+    Ext(u8),
+}
+
+impl Insn {
+    pub fn of(opcode: u8) -> VMResult<Self> {
+        Ok(match opcode {
+            1 => Insn::Add,
+            2 => Insn::Mul,
+            3 => Insn::Input,
+            4 => Insn::Output,
+            5 => Insn::JumpIfTrue,
+            6 => Insn::JumpIfFalse,
+            7 => Insn::LessThan,
+            8 => Insn::Equals,
+            9 => Insn::SetBase,
+
+            99 => Insn::End,
+
+            i => return Err(Error::UnrecognisedOpcode(i)),
+        })
+    }
+
+    pub fn decode(word: Int) -> VMResult<(Self, [Mode; 3])> {
+        if word < 0 {
+            return Err(Error::UnrecognisedOpcode((word % 100) as u8));
+        }
+        let insn = Insn::of((word % 100) as u8)?;
+        let mode = |i, digit: Int| match i < insn.arity() {
+            true => Mode::of(digit as u8),
+            false => Ok(Mode::Position),
+        };
+        Ok((
+            insn,
+            [
+                mode(0, word / 100 % 10)?,
+                mode(1, word / 1000 % 10)?,
+                mode(2, word / 10000 % 10)?,
+            ],
+        ))
+    }
+
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Insn::Add => 1,
+            Insn::Mul => 2,
+            Insn::Input => 3,
+            Insn::Output => 4,
+            Insn::JumpIfTrue => 5,
+            Insn::JumpIfFalse => 6,
+            Insn::LessThan => 7,
+            Insn::Equals => 8,
+            Insn::SetBase => 9,
+            Insn::End => 99,
+            Insn::Ext(op) => *op,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Insn::Add | Insn::Mul | Insn::LessThan | Insn::Equals => 3,
+            Insn::JumpIfTrue | Insn::JumpIfFalse => 2,
+            Insn::Input | Insn::Output | Insn::SetBase => 1,
+            Insn::End | Insn::Ext(_) => 0,
+        }
+    }
+
+    pub fn writes(&self) -> bool {
+        matches!(
+            self,
+            Insn::Add | Insn::Mul | Insn::LessThan | Insn::Equals | Insn::Input
+        )
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Insn::Add => "ADD",
+            Insn::Mul => "MUL",
+            Insn::Input => "INPUT",
+            Insn::Output => "OUTPUT",
+            Insn::End => "END",
+            Insn::JumpIfTrue => "JUMP_IF_TRUE",
+            Insn::JumpIfFalse => "JUMP_IF_FALSE",
+            Insn::LessThan => "LESS_THAN",
+            Insn::Equals => "EQUALS",
+            Insn::SetBase => "SET_BASE",
+            Insn::Ext(_) => "EXT",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Decoded<W> {
+    insn: Insn,
+    modes: [Mode; 3],
+    params: [W; 3],
+}
+
+impl<W: Word> Decoded<W> {
+    fn at(mem: &Memory<W>, addr: usize) -> VMResult<Self> {
+        let word = |addr| mem.get(addr).ok_or(Error::UnterminatedProgram(addr));
+        let (insn, modes) = Insn::decode(word(addr)?.to_int()?)?;
+        let param = |i| match i < insn.arity() {
+            true => word(addr + i + 1),
+            false => Ok(W::zero()),
+        };
+        Ok(Decoded {
+            insn,
+            modes,
+            params: [param(0)?, param(1)?, param(2)?],
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn of(digit: u8) -> VMResult<Self> {
+        Ok(match digit {
+            0 => Mode::Position,
+            1 => Mode::Immediate,
+            2 => Mode::Relative,
+
+            d => return Err(Error::UnrecognisedMode(d)),
+        })
+    }
+
+    pub fn digit(&self) -> u8 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VM<W = Int> {
+    pub mem: Memory<W>,
+    insn: usize,
+    inbuf: VecDeque<W>,
+    relbase: Int,
+    tracer: Option<Arc<Mutex<dyn Tracer<W> + Send>>>,
+    breakpoints: HashSet<usize>,
+    watchpoints: HashMap<usize, Access>,
+    resume: Option<(usize, Resume)>,
+    executed: usize,
+    // stopped at an END that's been counted, so it isn't run again
+    halted: bool,
+    fuel: Option<usize>,
+    counts: Option<Box<Counts>>,
+    touched: Cell<usize>,
+    extensions: HashMap<u8, Extension<W>>,
+    code: Option<Arc<Code<W>>>,
+    undo: Option<UndoLog<W>>,
+}
+
+// The most recently executed instructions, and how many times each opcode has
+// been executed, since `from` instructions in.
+#[derive(Clone)]
+struct Counts {
+    from: usize,
+    history: [usize; HISTORY],
+    histogram: [usize; OPCODES],
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Resume {
+    Breakpoint,
+    All,
+}
+
+impl VM {
+    pub fn of(program: &Program) -> Self {
+        VM::new(program)
+    }
+
+    // Like `VM::of`, but runs the program compiled to closures where it can.
+    pub fn compiled(program: &Program) -> Self {
+        VM::new(program).with_compiled_code()
+    }
+}
+
+impl<W: Word> VM<W> {
+    // Like `VM::of`, but for any word type, e.g. `VM::<BigInt>::new(&program)`.
+    pub fn new(program: &Program) -> Self {
+        VM::with_memory(Memory::dense(W::from_ints(&program.instructions)))
+    }
+
+    fn with_memory(mem: Memory<W>) -> Self {
+        VM {
+            mem,
+            insn: 0,
+            inbuf: VecDeque::new(),
+            relbase: 0,
+            tracer: None,
+            breakpoints: HashSet::new(),
+            watchpoints: HashMap::new(),
+            resume: None,
+            executed: 0,
+            halted: false,
+            fuel: None,
+            counts: None,
+            touched: Cell::new(0),
+            extensions: HashMap::new(),
+            code: None,
+            undo: None,
+        }
+    }
+
+    pub fn with_paged_memory(mut self) -> Self {
+        self.mem = self.mem.into_paged();
+        self
+    }
+
+    pub fn with_memory_limit(mut self, limit: usize) -> Self {
+        self.mem = self.mem.with_limit(limit);
+        self
+    }
+
+    pub fn with_decode_cache(mut self) -> Self {
+        self.mem = self.mem.with_decode_cache();
+        self
+    }
+
+    pub fn with_compiled_code(mut self) -> Self {
+        let (code, cells) = Code::compile(&self.mem);
+        self.mem = self.mem.with_code_guard(cells);
+        self.code = Some(Arc::new(code));
+        self
+    }
+
+    // Runs `opcode`, which mustn't be one of the standard ones, as an extension.
+    pub fn with_extension(mut self, opcode: u8, extension: Extension<W>) -> Self {
+        assert!(
+            (opcode as usize) < OPCODES && Insn::of(opcode).is_err(),
+            "opcode {} can't be used for an extension",
+            opcode
+        );
+        self.extensions.insert(opcode, extension);
+        self
+    }
+
+    // Each instruction executed burns one unit of fuel, and once it's all gone
+    // the VM stops with `State::OutOfFuel` until it's given more.
+    pub fn with_fuel(mut self, fuel: usize) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    pub fn executed(&self) -> usize {
+        self.executed
+    }
+
+    // Keeps the histogram, and the last few instructions executed for errors to
+    // show, from here on. Neither is kept otherwise, to save the time.
+    pub fn with_counts(mut self) -> Self {
+        self.counts = Some(Box::new(Counts {
+            from: self.executed,
+            history: [0; HISTORY],
+            histogram: [0; OPCODES],
+        }));
+        self
+    }
+
+    // How many times each instruction has been executed since `with_counts`,
+    // leaving out the ones that never were.
+    pub fn histogram(&self) -> Vec<(Insn, usize)> {
+        let counts = match &self.counts {
+            Some(counts) => counts,
+            None => return Vec::new(),
+        };
+        (0..OPCODES as u8)
+            .map(|op| (op, counts.histogram[op as usize]))
+            .filter(|&(_, n)| n > 0)
+            .map(|(op, n)| (Insn::of(op).unwrap_or(Insn::Ext(op)), n))
+            .collect()
+    }
+
+    // One past the highest address read or written through an operand.
+    pub fn memory_touched(&self) -> usize {
+        self.touched.get()
+    }
+
+    pub fn ip(&self) -> usize {
+        self.insn
+    }
+
+    pub fn relbase(&self) -> Int {
+        self.relbase
+    }
+
+    pub fn set_tracer(&mut self, tracer: Arc<Mutex<dyn Tracer<W> + Send>>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, addr: usize, access: Access) {
+        self.watchpoints.insert(addr, access);
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: usize) -> bool {
+        self.watchpoints.remove(&addr).is_some()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, Access)> + '_ {
+        self.watchpoints
+            .iter()
+            .map(|(&addr, &access)| (addr, access))
+    }
+
+    pub fn next_state(&mut self) -> ExecResult<State<W>, W> {
+        loop {
+            if self.is_plain() {
+                if let Some(state) = self.run() {
+                    return Ok(state);
+                }
+            }
+            if let Some(state) = self.exec()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    // Executes exactly one instruction, even if a breakpoint or watchpoint
+    // would otherwise stop it.
+    pub fn step(&mut self) -> ExecResult<Option<State<W>>, W> {
+        self.resume = Some((self.insn, Resume::All));
+        self.exec()
+    }
+
+    fn exec(&mut self) -> ExecResult<Option<State<W>>, W> {
+        if self.halted {
+            return Ok(Some(State::Finished));
+        }
+        let mut insn = self.insn;
+        let result = match self.code.take() {
+            Some(code) => {
+                let result = code.run(self, &mut insn);
+                self.code = Some(code);
+                result
+            }
+            None => self.advance(),
+        };
+        result.map_err(|error| {
+            let start = insn.saturating_sub(ERROR_WINDOW).min(self.mem.len());
+            let end = (insn + ERROR_WINDOW + 1).min(self.mem.len()).max(start);
+            ExecError {
+                mem: (start..end).map(|addr| self.mem[addr].clone()).collect(),
+                start,
+                len: self.mem.len(),
+                relbase: self.relbase,
+                history: match &self.counts {
+                    Some(counts) => (self.executed.saturating_sub(HISTORY).max(counts.from)
+                        ..self.executed)
+                        .map(|n| counts.history[n % HISTORY])
+                        .collect(),
+                    None => Vec::new(),
+                },
+                error,
+                insn,
+            }
+        })
+    }
+
+    // Whether the VM is at an END, so running it can only finish. Anything
+    // else, extensions and bad instructions included, still has to be run to
+    // find out what it does.
+    pub fn is_finished(&self) -> bool {
+        self.halted
+            || matches!(
+                self.peek().and_then(|o| o.to_int()),
+                Ok(o) if o % 100 == Insn::End.opcode() as Int
+            )
+    }
+
+    pub fn input(&mut self, input: W) {
+        self.inbuf.push_back(input);
+    }
+
+    pub fn input_ascii(&mut self, input: &str) {
+        for c in input.chars() {
+            self.input(W::from(c as Int));
+        }
+    }
+
+    fn peek(&self) -> VMResult<W> {
+        self.mem
+            .get(self.insn)
+            .ok_or(Error::UnterminatedProgram(self.insn))
+    }
+
+    fn address(&self, mode: Mode, v: &W) -> VMResult<usize> {
+        let t = match mode {
+            Mode::Position => v.to_int()?,
+            Mode::Relative => self
+                .relbase
+                .checked_add(v.to_int()?)
+                .ok_or(Error::Overflow)?,
+            Mode::Immediate => return Err(Error::UnsupportedSet(mode)),
+        };
+        if t < 0 {
+            return Err(Error::MemoryOutOfBounds(t));
+        }
+        self.touch(t as usize);
+        Ok(t as usize)
+    }
+
+    #[inline]
+    fn touch(&self, addr: usize) {
+        if addr >= self.touched.get() {
+            self.touched.set(addr + 1);
+        }
+    }
+
+    fn get(&self, mode: Mode, v: &W) -> VMResult<W> {
+        if mode == Mode::Immediate {
+            return Ok(v.clone());
+        }
+        let addr = self.address(mode, v)?;
+        self.mem.load(addr)
+    }
+
+    fn set(&mut self, mode: Mode, v: &W, val: W) -> VMResult<(usize, W)> {
+        let addr = self.address(mode, v)?;
+        let old = self
+            .undo
+            .as_ref()
+            .map(|_| (self.mem[addr].clone(), self.mem.len()));
+        self.mem.store(addr, val.clone())?;
+        if let (Some(undo), Some((old, len))) = (&mut self.undo, old) {
+            undo.write(addr, old, len);
+        }
+        Ok((addr, val))
+    }
+
+    fn jump(&mut self, to: &W) -> VMResult<()> {
+        let to = to.to_int()?;
+        if to < 0 {
+            Err(Error::MemoryOutOfBounds(to))
+        } else {
+            self.insn = to as usize;
+            Ok(())
+        }
+    }
+
+    // Breakpoints and watchpoints stop before the instruction is executed;
+    // `resume` lets it through the next time around.
+    fn check_watch(&self, addr: usize, access: Access) -> Option<State<W>> {
+        match self.watchpoints.get(&addr) {
+            Some(&watch) if watch == access || watch == Access::ReadWrite => {
+                Some(State::Watch(addr, access))
+            }
+            _ => None,
+        }
+    }
+
+    fn pause(&mut self, addr: usize, state: State<W>, resume: Resume) -> Option<State<W>> {
+        self.insn = addr;
+        self.resume = Some((addr, resume));
+        Some(state)
+    }
+
+    fn advance(&mut self) -> VMResult<Option<State<W>>> {
+        let addr = self.insn;
+        let resume = match self.resume.take() {
+            Some((at, resume)) if at == addr => Some(resume),
+            _ => None,
+        };
+        if resume.is_none() && !self.breakpoints.is_empty() && self.breakpoints.contains(&addr) {
+            let state = State::Breakpoint(addr);
+            return Ok(self.pause(addr, state, Resume::Breakpoint));
+        }
+
+        let Decoded {
+            insn,
+            modes,
+            params,
+        } = match self.mem.decoded(addr) {
+            Some(decoded) => decoded,
+            None => match Decoded::at(&self.mem, addr) {
+                Ok(decoded) => decoded,
+                Err(Error::UnrecognisedOpcode(op)) if self.extensions.contains_key(&op) => {
+                    return self.extended(addr, op, resume);
+                }
+                Err(e) => return Err(e),
+            },
+        };
+        let arity = insn.arity();
+        let reads = arity - insn.writes() as usize;
+        self.insn = addr + arity + 1;
+        if insn == Insn::Input && self.inbuf.is_empty() {
+            self.insn = addr;
+            self.resume = resume.map(|resume| (addr, resume));
+            return Ok(Some(State::AwaitingInput));
+        }
+        if self.fuel == Some(0) && insn != Insn::End {
+            self.insn = addr;
+            self.resume = resume.map(|resume| (addr, resume));
+            return Ok(Some(State::OutOfFuel));
+        }
+
+        if resume != Some(Resume::All) {
+            if let Some(state) = self.check_operands(&modes[..arity], &params[..arity], reads)? {
+                return Ok(self.pause(addr, state, Resume::All));
+            }
+        }
+        let arg = |i| self.get(modes[i], &params[i]);
+        let flag = |b| if b { W::one() } else { W::zero() };
+        let mut args = [W::zero(), W::zero()];
+        let mut state = None;
+        let mut write = None;
+        let old_base = self.relbase;
+        match insn {
+            Insn::Add => {
+                args = [arg(0)?, arg(1)?];
+                let sum = args[0].checked_add(&args[1]).ok_or(Error::Overflow)?;
+                write = Some(self.set(modes[2], &params[2], sum)?);
+            }
+            Insn::Mul => {
+                args = [arg(0)?, arg(1)?];
+                let prod = args[0].checked_mul(&args[1]).ok_or(Error::Overflow)?;
+                write = Some(self.set(modes[2], &params[2], prod)?);
+            }
+            Insn::Input => {
+                let input = self.inbuf.pop_front().unwrap();
+                write = Some(self.set(modes[0], &params[0], input)?);
+            }
+            Insn::Output => {
+                args[0] = arg(0)?;
+                state = Some(State::Outputting(args[0].clone()));
+            }
+            Insn::JumpIfTrue => {
+                args = [arg(0)?, arg(1)?];
+                if !args[0].is_zero() {
+                    self.jump(&args[1])?;
+                }
+            }
+            Insn::JumpIfFalse => {
+                args = [arg(0)?, arg(1)?];
+                if args[0].is_zero() {
+                    self.jump(&args[1])?;
+                }
+            }
+            Insn::LessThan => {
+                args = [arg(0)?, arg(1)?];
+                let less = flag(args[0] < args[1]);
+                write = Some(self.set(modes[2], &params[2], less)?);
+            }
+            Insn::Equals => {
+                args = [arg(0)?, arg(1)?];
+                let equal = flag(args[0] == args[1]);
+                write = Some(self.set(modes[2], &params[2], equal)?);
+            }
+            Insn::SetBase => {
+                args[0] = arg(0)?;
+                self.relbase = self
+                    .relbase
+                    .checked_add(args[0].to_int()?)
+                    .ok_or(Error::Overflow)?;
+            }
+
+            Insn::End => {
+                self.insn = addr; // keep the program terminated
+                self.halted = true;
+                state = Some(State::Finished);
+            }
+            // only ever decoded by `extended`
+            Insn::Ext(_) => return Err(Error::UnsupportedOperation),
+        }
+
+        let relbase = if self.relbase != old_base {
+            Some(self.relbase)
+        } else {
+            None
+        };
+        self.retire(Step {
+            addr,
+            insn,
+            modes: &modes[..arity],
+            params: &params[..arity],
+            args: &args[..reads],
+            write,
+            relbase,
+            next: self.insn,
+        });
+        Ok(state)
+    }
+
+    fn extended(
+        &mut self,
+        addr: usize,
+        opcode: u8,
+        resume: Option<Resume>,
+    ) -> VMResult<Option<State<W>>> {
+        let ext = &self.extensions[&opcode];
+        let (reads, arity, handler) = (ext.reads(), ext.arity(), ext.handler());
+        let mut digits = self.peek()?.to_int()? / 100;
+        let mut modes = Vec::with_capacity(arity);
+        let mut params = Vec::with_capacity(arity);
+        for i in addr + 1..=addr + arity {
+            modes.push(Mode::of((digits % 10) as u8)?);
+            params.push(self.mem.get(i).ok_or(Error::UnterminatedProgram(i))?);
+            digits /= 10;
+        }
+        if self.fuel == Some(0) {
+            self.resume = resume.map(|resume| (addr, resume));
+            return Ok(Some(State::OutOfFuel));
+        }
+        if resume != Some(Resume::All) {
+            if let Some(state) = self.check_operands(&modes, &params, reads)? {
+                return Ok(self.pause(addr, state, Resume::All));
+            }
+        }
+
+        let args = (0..reads)
+            .map(|i| self.get(modes[i], &params[i]))
+            .collect::<VMResult<Vec<_>>>()?;
+        let result = (*handler.lock().unwrap())(&args)?;
+        let write = match result {
+            Some(val) if arity > reads => Some(self.set(modes[reads], &params[reads], val)?),
+            _ => None,
+        };
+        self.insn = addr + arity + 1;
+        self.retire(Step {
+            addr,
+            insn: Insn::Ext(opcode),
+            modes: &modes,
+            params: &params,
+            args: &args,
+            write,
+            relbase: None,
+            next: self.insn,
+        });
+        Ok(None)
+    }
+
+    // Whether there's nothing that needs to see each instruction as it's
+    // executed, so `run` can take over from `advance`.
+    fn is_plain(&self) -> bool {
+        !self.halted
+            && self.code.is_none()
+            && self.tracer.is_none()
+            && self.undo.is_none()
+            && self.resume.is_none()
+            && self.breakpoints.is_empty()
+            && self.watchpoints.is_empty()
+            && !self.mem.is_cached()
+    }
+
+    // Executes instructions straight from memory until one outputs or needs
+    // input or the program ends. Anything it doesn't handle, from extensions
+    // and running out of fuel to every kind of error, is left unexecuted for
+    // `advance`.
+    fn run(&mut self) -> Option<State<W>> {
+        loop {
+            if self.fuel == Some(0) {
+                return None;
+            }
+            let addr = self.insn;
+            let word = self.mem.words().get(addr)?.to_int().ok()?;
+            let op = word % 100;
+            let modes = [word / 100 % 10, word / 1000 % 10, word / 10000 % 10];
+            let arg = |i: usize| self.operand(addr + i + 1, modes[i]);
+            let flag = |b| if b { W::one() } else { W::zero() };
+            let (insn, write, next) = match op {
+                1 => (Insn::Add, arg(0)?.checked_add(&arg(1)?)?, addr + 4),
+                2 => (Insn::Mul, arg(0)?.checked_mul(&arg(1)?)?, addr + 4),
+                3 if self.inbuf.is_empty() && modes[0] <= 2 && self.mem.len() > addr + 1 => {
+                    return Some(State::AwaitingInput);
+                }
+                3 => (Insn::Input, self.inbuf.front()?.clone(), addr + 2),
+                4 => {
+                    let output = arg(0)?;
+                    self.insn = addr + 2;
+                    self.count(addr, Insn::Output);
+                    return Some(State::Outputting(output));
+                }
+                5 | 6 => {
+                    let (test, to) = (arg(0)?, arg(1)?);
+                    self.insn = match test.is_zero() == (op == 6) {
+                        true => usize::try_from(to.to_int().ok()?).ok()?,
+                        false => addr + 3,
+                    };
+                    self.count(addr, Insn::of(op as u8).ok()?);
+                    continue;
+                }
+                7 => (Insn::LessThan, flag(arg(0)? < arg(1)?), addr + 4),
+                8 => (Insn::Equals, flag(arg(0)? == arg(1)?), addr + 4),
+                9 => {
+                    self.relbase = self.relbase.checked_add(arg(0)?.to_int().ok()?)?;
+                    self.insn = addr + 2;
+                    self.count(addr, Insn::SetBase);
+                    continue;
+                }
+                99 => {
+                    self.halted = true;
+                    self.count(addr, Insn::End);
+                    return Some(State::Finished);
+                }
+                _ => return None,
+            };
+            let digit = modes[next - addr - 2];
+            let to = self.target(self.mem.words().get(next - 1)?, digit)?;
+            self.mem.store(to, write).ok()?;
+            if insn == Insn::Input {
+                self.inbuf.pop_front();
+            }
+            self.insn = next;
+            self.count(addr, insn);
+        }
+    }
+
+    // The value of the operand at `at` in mode `digit`, for `run`.
+    #[inline]
+    fn operand(&self, at: usize, digit: Int) -> Option<W> {
+        let param = self.mem.words().get(at)?;
+        match digit {
+            1 => Some(param.clone()),
+            _ => self.mem.load(self.target(param, digit)?).ok(),
+        }
+    }
+
+    // The address an operand in mode `digit` refers to, for `run`.
+    #[inline]
+    fn target(&self, param: &W, digit: Int) -> Option<usize> {
+        let addr = match digit {
+            0 => param.to_int().ok()?,
+            2 => self.relbase.checked_add(param.to_int().ok()?)?,
+            _ => return None,
+        };
+        let addr = usize::try_from(addr).ok()?;
+        self.touch(addr);
+        Some(addr)
+    }
+
+    // Watchpoints on the cells an instruction's operands read and write.
+    fn check_operands(
+        &self,
+        modes: &[Mode],
+        params: &[W],
+        reads: usize,
+    ) -> VMResult<Option<State<W>>> {
+        if self.watchpoints.is_empty() {
+            return Ok(None);
+        }
+        for (i, (&mode, param)) in modes.iter().zip(params).enumerate() {
+            if mode == Mode::Immediate {
+                continue;
+            }
+            let access = if i < reads {
+                Access::Read
+            } else {
+                Access::Write
+            };
+            if let Some(state) = self.check_watch(self.address(mode, param)?, access) {
+                return Ok(Some(state));
+            }
+        }
+        Ok(None)
+    }
+
+    // Counts an executed instruction and hands it to the tracer.
+    fn retire(&mut self, step: Step<W>) {
+        if let Some(undo) = &mut self.undo {
+            let slot = self.executed % HISTORY;
+            let history = self
+                .counts
+                .as_ref()
+                .map_or(0, |counts| counts.history[slot]);
+            undo.retire(&step, self.relbase, self.touched.get(), history);
+        }
+        self.count(step.addr, step.insn);
+        if let Some(tracer) = &self.tracer {
+            tracer.lock().unwrap().trace(&step);
+        }
+    }
+
+    #[inline]
+    fn count(&mut self, addr: usize, insn: Insn) {
+        if let Some(counts) = &mut self.counts {
+            counts.history[self.executed % HISTORY] = addr;
+            counts.histogram[insn.opcode() as usize] += 1;
+        }
+        self.executed += 1;
+        if insn != Insn::End {
+            self.fuel = self.fuel.map(|fuel| fuel - 1);
+        }
+    }
+}
+
+// Writes memory as a list of words, marking the instruction at `insn` and
+// any gap in the addresses with `...`.
+fn format_mem<W, I>(f: &mut Formatter, mem: I, insn: usize) -> fmt::Result
+where
+    W: Word,
+    I: Iterator<Item = (usize, W)>,
+{
+    let mut next = None;
+    for (addr, val) in mem {
+        if next.is_some() && next != Some(addr) {
+            write!(f, "...,")?;
+        }
+        next = Some(addr + 1);
+        if addr == insn {
+            write!(f, "[{}],", val)?;
+        } else {
+            write!(f, "{},", val)?;
+        }
+    }
+    Ok(())
+}
+
+impl<W: Word> Debug for VM<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let cells = self.mem.cells().map(|(addr, w)| (addr, w.clone()));
+        format_mem(f, cells, self.insn)?;
+        write!(f, "END")
+    }
+}
+
+impl<W: Word> ExecError<W> {
+    // The address of the instruction that failed.
+    pub fn addr(&self) -> usize {
+        self.insn
+    }
+
+    pub fn error(&self) -> Error {
+        self.error
+    }
+
+    pub fn relbase(&self) -> Int {
+        self.relbase
+    }
+
+    // Addresses of the instructions executed just before the failure, oldest
+    // first.
+    pub fn history(&self) -> &[usize] {
+        &self.history
+    }
+
+    // The memory around the failing instruction, and the address it starts at.
+    pub fn window(&self) -> (usize, &[W]) {
+        (self.start, &self.mem)
+    }
+
+    fn format_window(&self, f: &mut Formatter, radius: usize) -> fmt::Result {
+        let start = self.start.max(self.insn.saturating_sub(radius));
+        let end = (self.start + self.mem.len()).min(self.insn + radius + 1);
+        if start > 0 {
+            write!(f, "...({}),", start)?;
+        }
+        let window = (start..end).map(|addr| (addr, self.mem[addr - self.start].clone()));
+        format_mem(f, window, self.insn)?;
+        if end < self.len {
+            write!(f, "...")
+        } else {
+            write!(f, "END")
+        }
+    }
+}
+
+impl<W: Word> Debug for ExecError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:?}", self.error)?;
+        self.format_window(f, ERROR_WINDOW)
+    }
+}
+
+impl<W: Word> Display for ExecError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} at {} (relative base {})",
+            self.error, self.insn, self.relbase
+        )?;
+        if !self.history.is_empty() {
+            writeln!(f, "after {}", self.history.iter().join(", "))?;
+        }
+        self.format_window(f, DISPLAY_WINDOW)
+    }
+}
+
+impl<W: Word> error::Error for ExecError<W> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnrecognisedMode(mode) => write!(f, "unrecognised parameter mode {}", mode),
+            Error::UnrecognisedOpcode(op) => write!(f, "unrecognised opcode {}", op),
+            Error::UnsupportedOperation => write!(f, "unsupported operation"),
+            Error::MemoryOutOfBounds(addr) => write!(f, "address {} is out of bounds", addr),
+            Error::UnterminatedProgram(addr) => {
+                write!(f, "program runs off the end of memory at {}", addr)
+            }
+            Error::UnsupportedSet(mode) => write!(f, "can't write to a {:?} parameter", mode),
+            Error::Overflow => write!(f, "arithmetic overflow"),
+            Error::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl Display for ParseProgramError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseProgramError::NotInteger(at, s, e) => write!(
+                f,
+                "line {}, column {}: word {} ({:?}) is not an integer: {}",
+                at.line, at.column, at.index, s, e
+            ),
+            ParseProgramError::IOError(e) => write!(f, "couldn't read program: {}", e),
+        }
+    }
+}
+
+impl error::Error for ParseProgramError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseProgramError::NotInteger(_, _, e) => Some(e),
+            ParseProgramError::IOError(e) => Some(e),
+        }
+    }
+}
+
+impl<W: Word> Iterator for VM<W> {
+    type Item = W;
+
+    fn next(&mut self) -> Option<W> {
+        match self.next_state() {
+            Ok(s) => match s {
+                State::Outputting(i) => Some(i),
+                _ => None,
+            },
+            Err(_) => None,
+        }
+    }
+}
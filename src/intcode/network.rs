@@ -0,0 +1,406 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use crate::intcode::word::Word;
+use crate::intcode::{ExecError, Int, State, VM};
+
+// A network of VMs that talk in packets. A VM sends a packet by outputting a
+// frame of words, the first being the address it's for and the rest the
+// payload; a packet delivered to a VM is queued up as its input, payload only.
+//
+// A VM waiting for input with nothing queued is given the idle input, if there
+// is one. It counts as idle once it comes back for more input without having
+// sent anything in between, and the network is idle when all of its VMs are,
+// at which point the router gets a chance to wake it up again.
+//
+// With a time slice, each VM gets that much fuel per turn, so one that's busy
+// computing can't hold up the rest of the network.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Packet<W = Int> {
+    pub from: Int,
+    pub to: Int,
+    pub payload: Vec<W>,
+}
+
+pub trait Router<W = Int> {
+    // Every packet sent goes through here on its way to its destination, and
+    // can be rewritten, or kept by returning `None`. Packets for addresses that
+    // aren't on the network are dropped.
+    fn route(&mut self, packet: Packet<W>) -> Option<Packet<W>> {
+        Some(packet)
+    }
+
+    // Called whenever the network goes idle. A packet returned is delivered,
+    // while `None` ends the run.
+    fn idle(&mut self) -> Option<Packet<W>> {
+        None
+    }
+}
+
+// Delivers everything as addressed, and stops when the network goes idle.
+pub struct Direct;
+
+impl<W> Router<W> for Direct {}
+
+// Keeps packets sent to its address, and when the network goes idle sends the
+// last of them on to its target. It stops once it would send the same payload
+// twice in a row, since the network would only do the same thing again.
+pub struct Nat<W = Int> {
+    address: Int,
+    target: Int,
+    first: Option<Packet<W>>,
+    last: Option<Packet<W>>,
+    sent: Vec<Packet<W>>,
+}
+
+impl<W> Nat<W> {
+    pub fn new(address: Int, target: Int) -> Self {
+        Nat {
+            address,
+            target,
+            first: None,
+            last: None,
+            sent: Vec::new(),
+        }
+    }
+
+    pub fn first(&self) -> Option<&Packet<W>> {
+        self.first.as_ref()
+    }
+
+    pub fn sent(&self) -> &[Packet<W>] {
+        &self.sent
+    }
+}
+
+impl<W: Word> Router<W> for Nat<W> {
+    fn route(&mut self, packet: Packet<W>) -> Option<Packet<W>> {
+        if packet.to != self.address {
+            return Some(packet);
+        }
+        if self.first.is_none() {
+            self.first = Some(packet.clone());
+        }
+        self.last = Some(packet);
+        None
+    }
+
+    fn idle(&mut self) -> Option<Packet<W>> {
+        let payload = self.last.as_ref()?.payload.clone();
+        if self.sent.last().map(|p| &p.payload) == Some(&payload) {
+            return None;
+        }
+        let packet = Packet {
+            from: self.address,
+            to: self.target,
+            payload,
+        };
+        self.sent.push(packet.clone());
+        Some(packet)
+    }
+}
+
+struct Node<W> {
+    address: Int,
+    vm: VM<W>,
+    frame: Vec<W>,
+    inbox: VecDeque<W>,
+    fed_idle: bool,
+    preempted: bool,
+    finished: bool,
+}
+
+impl<W: Word> Node<W> {
+    // Runs the VM until it wants input, finishes or runs out of fuel,
+    // collecting what it sends. With `eager`, it also stops as soon as it's
+    // sent a packet, returning true, so the packet can be delivered while the
+    // VM carries on.
+    fn run(
+        &mut self,
+        frame: usize,
+        sent: &mut Vec<Packet<W>>,
+        eager: bool,
+    ) -> Result<bool, ExecError<W>> {
+        self.preempted = false;
+        loop {
+            match self.vm.next_state()? {
+                State::Outputting(w) => {
+                    self.fed_idle = false;
+                    self.frame.push(w);
+                    if self.frame.len() == frame {
+                        let mut words = mem::take(&mut self.frame).into_iter();
+                        if let Some(to) = words.next().and_then(|w| w.to_i64()) {
+                            sent.push(Packet {
+                                from: self.address,
+                                to,
+                                payload: words.collect(),
+                            });
+                            if eager {
+                                return Ok(true);
+                            }
+                        }
+                    }
+                }
+                State::AwaitingInput => return Ok(false),
+                State::Finished => {
+                    self.finished = true;
+                    return Ok(false);
+                }
+                State::OutOfFuel => {
+                    self.preempted = true;
+                    return Ok(false);
+                }
+                State::Breakpoint(_) | State::Watch(_, _) => (),
+            }
+        }
+    }
+
+    // Gives a waiting VM its next input, returning false if it's idle.
+    fn feed(&mut self, inbox: &mut VecDeque<W>, idle_input: &Option<W>) -> bool {
+        if !inbox.is_empty() {
+            inbox.drain(..).for_each(|w| self.vm.input(w));
+            self.fed_idle = false;
+            return true;
+        }
+        match idle_input {
+            Some(w) if !self.fed_idle => {
+                self.vm.input(w.clone());
+                self.fed_idle = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct Network<W = Int> {
+    nodes: Vec<Node<W>>,
+    addresses: HashMap<Int, usize>,
+    frame: usize,
+    idle_input: Option<W>,
+    slice: Option<usize>,
+}
+
+impl<W: Word> Network<W> {
+    // `frame` is the number of outputs that make up a packet, address included.
+    pub fn new(frame: usize) -> Self {
+        Network {
+            nodes: Vec::new(),
+            addresses: HashMap::new(),
+            frame,
+            idle_input: None,
+            slice: None,
+        }
+    }
+
+    pub fn with_idle_input(mut self, idle_input: W) -> Self {
+        self.idle_input = Some(idle_input);
+        self
+    }
+
+    pub fn with_time_slice(mut self, slice: usize) -> Self {
+        self.slice = Some(slice);
+        self
+    }
+
+    pub fn add(&mut self, address: Int, vm: VM<W>) {
+        self.addresses.insert(address, self.nodes.len());
+        self.nodes.push(Node {
+            address,
+            vm,
+            frame: Vec::new(),
+            inbox: VecDeque::new(),
+            fed_idle: false,
+            preempted: false,
+            finished: false,
+        });
+    }
+
+    pub fn vm(&self, address: Int) -> Option<&VM<W>> {
+        Some(&self.nodes[*self.addresses.get(&address)?].vm)
+    }
+
+    // Delivers a packet straight to its destination, bypassing the router.
+    pub fn send(&mut self, packet: Packet<W>) {
+        if let Some(&i) = self.addresses.get(&packet.to) {
+            self.nodes[i].inbox.extend(packet.payload);
+        }
+    }
+
+    // Runs the VMs one after another, each until it's idle, until the whole
+    // network is. The order things happen in only depends on the programs.
+    pub fn run_until_idle<R: Router<W>>(&mut self, router: &mut R) -> Result<(), ExecError<W>> {
+        let mut sent = Vec::new();
+        loop {
+            let mut busy = false;
+            for i in 0..self.nodes.len() {
+                let node = &mut self.nodes[i];
+                let mut inbox = mem::take(&mut node.inbox);
+                if self.slice.is_some() {
+                    node.vm.set_fuel(self.slice);
+                }
+                while !node.finished {
+                    node.run(self.frame, &mut sent, false)?;
+                    if node.preempted {
+                        // without time slices, a VM out of fuel stays parked
+                        busy |= self.slice.is_some();
+                        break;
+                    }
+                    if !node.feed(&mut inbox, &self.idle_input) {
+                        break;
+                    }
+                }
+                node.inbox = inbox;
+                for packet in sent.drain(..).filter_map(|p| router.route(p)) {
+                    if let Some(&to) = self.addresses.get(&packet.to) {
+                        if !self.nodes[to].finished {
+                            self.nodes[to].inbox.extend(packet.payload);
+                            busy = true;
+                        }
+                    }
+                }
+            }
+            if !busy {
+                return Ok(());
+            }
+        }
+    }
+
+    // Runs until the network is idle and the router has nothing more to send.
+    pub fn run<R: Router<W>>(&mut self, router: &mut R) -> Result<(), ExecError<W>> {
+        loop {
+            self.run_until_idle(router)?;
+            match router.idle() {
+                Some(packet) => self.send(packet),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // Like `run`, but with every VM on its own thread.
+    pub fn run_threaded<R>(&mut self, router: &mut R) -> Result<(), ExecError<W>>
+    where
+        R: Router<W> + Send,
+    {
+        let Network {
+            nodes,
+            addresses,
+            frame,
+            idle_input,
+            slice,
+        } = self;
+        let shared = Mutex::new(Shared {
+            inboxes: nodes.iter_mut().map(|n| mem::take(&mut n.inbox)).collect(),
+            idle: vec![false; nodes.len()],
+            finished: nodes.iter().map(|n| n.finished).collect(),
+            addresses,
+            router,
+            done: false,
+            error: None,
+        });
+        let wake = Condvar::new();
+        let (frame, idle_input, slice) = (*frame, &*idle_input, *slice);
+        thread::scope(|scope| {
+            for (i, node) in nodes.iter_mut().enumerate() {
+                let (shared, wake) = (&shared, &wake);
+                scope.spawn(move || {
+                    let mut sent = Vec::new();
+                    let mut sending = false;
+                    loop {
+                        // a VM that only stopped to send carries on with its slice
+                        if slice.is_some() && !sending {
+                            node.vm.set_fuel(slice);
+                        }
+                        let result = node.run(frame, &mut sent, true);
+                        let mut shared = shared.lock().unwrap();
+                        if !sent.is_empty() {
+                            sent.drain(..).for_each(|p| shared.route(p));
+                            wake.notify_all();
+                        }
+                        sending = false;
+                        match result {
+                            Ok(stopped_to_send) => sending = stopped_to_send,
+                            Err(e) => {
+                                shared.done = true;
+                                shared.error.get_or_insert(e);
+                            }
+                        }
+                        if node.finished {
+                            shared.finished[i] = true;
+                            shared.idle[i] = true;
+                        }
+                        if (sending || node.preempted && slice.is_some()) && !shared.done {
+                            continue;
+                        }
+                        loop {
+                            if shared.done || node.finished {
+                                shared.settle();
+                                wake.notify_all();
+                                return;
+                            }
+                            if node.feed(&mut shared.inboxes[i], idle_input) {
+                                break;
+                            }
+                            shared.idle[i] = true;
+                            shared.settle();
+                            wake.notify_all();
+                            // settling may have ended the run, with no one left to wake us
+                            if !shared.done {
+                                shared = wake.wait(shared).unwrap();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let shared = shared.into_inner().unwrap();
+        for (node, inbox) in nodes.iter_mut().zip(shared.inboxes) {
+            node.inbox = inbox;
+        }
+        match shared.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Shared<'a, W, R> {
+    inboxes: Vec<VecDeque<W>>,
+    idle: Vec<bool>,
+    finished: Vec<bool>,
+    addresses: &'a HashMap<Int, usize>,
+    router: &'a mut R,
+    done: bool,
+    error: Option<ExecError<W>>,
+}
+
+impl<W: Word, R: Router<W>> Shared<'_, W, R> {
+    fn route(&mut self, packet: Packet<W>) {
+        if let Some(packet) = self.router.route(packet) {
+            self.deliver(packet);
+        }
+    }
+
+    fn deliver(&mut self, packet: Packet<W>) {
+        if let Some(&to) = self.addresses.get(&packet.to) {
+            if !self.finished[to] {
+                self.inboxes[to].extend(packet.payload);
+                self.idle[to] = false;
+            }
+        }
+    }
+
+    // Once every VM is idle, it's the router's turn.
+    fn settle(&mut self) {
+        while !self.done && self.idle.iter().all(|&idle| idle) {
+            match self.router.idle() {
+                Some(packet) => self.deliver(packet),
+                None => self.done = true,
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::error;
+use std::fmt::{self, Debug, Display, Formatter};
+
+use crate::intcode::word::Word;
+use crate::intcode::{ExecError, Int, State, VM};
+
+// VMs wired output-to-input. Each stage's outputs are copied to every stage
+// it's linked to, so besides `series` and `ring` any shape can be built up
+// with `with_link`. The outputs of one stage, the last unless set otherwise,
+// are kept as the pipeline's output. Links and outputs naming a stage that
+// isn't there are reported when the pipeline runs.
+pub struct Pipeline<W = Int> {
+    stages: Vec<VM<W>>,
+    // from and to
+    links: Vec<(usize, usize)>,
+    output: Option<usize>,
+    outputs: Vec<W>,
+}
+
+pub enum PipelineError<W = Int> {
+    // Every stage still running is waiting for input that nothing will send.
+    Deadlock(Vec<usize>),
+    Exec(usize, ExecError<W>),
+    OutOfFuel(usize),
+    NoOutput,
+    NoStage(usize),
+}
+
+impl<W: Word> Pipeline<W> {
+    pub fn new(stages: Vec<VM<W>>) -> Self {
+        Pipeline {
+            links: Vec::new(),
+            output: stages.len().checked_sub(1),
+            outputs: Vec::new(),
+            stages,
+        }
+    }
+
+    pub fn series(stages: Vec<VM<W>>) -> Self {
+        let n = stages.len();
+        (1..n).fold(Pipeline::new(stages), |p, i| p.with_link(i - 1, i))
+    }
+
+    pub fn ring(stages: Vec<VM<W>>) -> Self {
+        let n = stages.len();
+        match n {
+            0 => Pipeline::new(stages),
+            _ => Pipeline::series(stages).with_link(n - 1, 0),
+        }
+    }
+
+    pub fn with_link(mut self, from: usize, to: usize) -> Self {
+        self.links.push((from, to));
+        self
+    }
+
+    pub fn with_output(mut self, stage: usize) -> Self {
+        self.output = Some(stage);
+        self
+    }
+
+    // Gives each stage its phase setting, in order, as its first input.
+    pub fn with_phases<I: IntoIterator<Item = W>>(mut self, phases: I) -> Self {
+        for (vm, phase) in self.stages.iter_mut().zip(phases) {
+            vm.input(phase);
+        }
+        self
+    }
+
+    pub fn input(&mut self, stage: usize, value: W) -> Result<(), PipelineError<W>> {
+        let vm = self
+            .stages
+            .get_mut(stage)
+            .ok_or(PipelineError::NoStage(stage))?;
+        vm.input(value);
+        Ok(())
+    }
+
+    pub fn stage(&self, stage: usize) -> Option<&VM<W>> {
+        self.stages.get(stage)
+    }
+
+    pub fn outputs(&self) -> &[W] {
+        &self.outputs
+    }
+
+    // Runs every stage in turn until they've all finished, and returns the last
+    // output. Outputs sent to a stage that's already finished are dropped.
+    pub fn run(&mut self) -> Result<W, PipelineError<W>> {
+        let n = self.stages.len();
+        let stages = self.links.iter().flat_map(|&(from, to)| vec![from, to]);
+        if let Some(stage) = stages.chain(self.output).find(|&stage| stage >= n) {
+            return Err(PipelineError::NoStage(stage));
+        }
+        loop {
+            let mut progress = false;
+            for i in 0..self.stages.len() {
+                loop {
+                    match self.stages[i].next_state() {
+                        Ok(State::Outputting(w)) => {
+                            progress = true;
+                            for &(from, to) in &self.links {
+                                if from == i && !self.stages[to].is_finished() {
+                                    self.stages[to].input(w.clone());
+                                }
+                            }
+                            if Some(i) == self.output {
+                                self.outputs.push(w);
+                            }
+                        }
+                        Ok(State::AwaitingInput) | Ok(State::Finished) => break,
+                        Ok(State::Breakpoint(_)) | Ok(State::Watch(_, _)) => (),
+                        Ok(State::OutOfFuel) => return Err(PipelineError::OutOfFuel(i)),
+                        Err(e) => return Err(PipelineError::Exec(i, e)),
+                    }
+                }
+            }
+            let waiting = (0..self.stages.len())
+                .filter(|&i| !self.stages[i].is_finished())
+                .collect::<Vec<_>>();
+            if waiting.is_empty() {
+                return self.outputs.last().cloned().ok_or(PipelineError::NoOutput);
+            }
+            if !progress {
+                return Err(PipelineError::Deadlock(waiting));
+            }
+        }
+    }
+}
+
+impl<W: Word> Debug for PipelineError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Deadlock(stages) => write!(f, "Deadlock({:?})", stages),
+            PipelineError::Exec(stage, e) => write!(f, "Exec({}, {:?})", stage, e),
+            PipelineError::OutOfFuel(stage) => write!(f, "OutOfFuel({})", stage),
+            PipelineError::NoOutput => write!(f, "NoOutput"),
+            PipelineError::NoStage(stage) => write!(f, "NoStage({})", stage),
+        }
+    }
+}
+
+impl<W: Word> Display for PipelineError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Deadlock(stages) => {
+                write!(f, "deadlock: stages {:?} are all waiting for input", stages)
+            }
+            PipelineError::Exec(stage, e) => write!(f, "stage {} failed: {}", stage, e),
+            PipelineError::OutOfFuel(stage) => write!(f, "stage {} ran out of fuel", stage),
+            PipelineError::NoOutput => write!(f, "pipeline finished without any output"),
+            PipelineError::NoStage(stage) => write!(f, "there's no stage {}", stage),
+        }
+    }
+}
+
+impl<W: Word> error::Error for PipelineError<W> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PipelineError::Exec(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
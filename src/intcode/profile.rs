@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::mem;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use itertools::Itertools;
+
+use crate::intcode::cfg;
+use crate::intcode::disasm::Line;
+use crate::intcode::trace::{Step, Tracer};
+use crate::intcode::word::Word;
+use crate::intcode::{Insn, Int, Mode, VM};
+
+// Counts how often each address is executed and written while it's attached to
+// a VM, keeping the memory it started with to make listings from.
+//
+// Calls and returns are recognised as they are in `cfg`: a call is a jump
+// just after its return address was written through the relative base, and a
+// return is a jump through the relative base. Each call starts a frame at the
+// relative base it was made with, and a return ends the frames from there up,
+// so the frames give the stacks for a flame graph, one line each:
+//
+//   main;fn@1424;fn@2011 5170
+//
+// which `flamegraph.pl` and most other flame graph tools take as is. Any other
+// backward jump taken closes a loop.
+
+// How many loops the report lists.
+const HOT_LOOPS: usize = 10;
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    image: Vec<Int>,
+    counts: Vec<usize>,
+    writes: HashMap<usize, usize>,
+    // taken backward jumps, from and to
+    loops: HashMap<(usize, usize), usize>,
+    stacks: HashMap<Vec<usize>, usize>,
+    // the functions called and the relative base each was called with,
+    // innermost last
+    frames: Vec<(usize, Int)>,
+    relbase: Int,
+    // values written through the relative base since the last jump
+    pushed: Vec<Int>,
+    // instructions executed since the stack last changed
+    run: usize,
+}
+
+// A loop, from the address a backward jump goes to up to the jump.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Loop {
+    pub head: usize,
+    pub tail: usize,
+    pub iterations: usize,
+    pub executed: usize,
+}
+
+// Cells written while profiling, and how many writes there were between them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Region {
+    pub cells: Range<usize>,
+    pub writes: usize,
+}
+
+impl Profile {
+    pub fn new(image: Vec<Int>, relbase: Int) -> Self {
+        Profile {
+            image,
+            relbase,
+            ..Profile::default()
+        }
+    }
+
+    pub fn executed(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn count(&self, addr: usize) -> usize {
+        self.counts.get(addr).copied().unwrap_or(0)
+    }
+
+    // Adds in another profile of the same program, such as one from another VM
+    // running it. An empty profile takes the other's starting memory.
+    pub fn merge(&mut self, other: &Profile) {
+        if self.image.is_empty() {
+            self.image = other.image.clone();
+        }
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        for (&addr, &writes) in &other.writes {
+            *self.writes.entry(addr).or_default() += writes;
+        }
+        for (&edge, &iterations) in &other.loops {
+            *self.loops.entry(edge).or_default() += iterations;
+        }
+        for (stack, count) in other.collapsed() {
+            *self.stacks.entry(stack).or_default() += count;
+        }
+    }
+
+    // The loops that went round the most, hottest first. What they executed
+    // is counted by address, from the head to the jump back, so it takes in
+    // any loops nested inside but not the functions they call.
+    pub fn hot_loops(&self) -> Vec<Loop> {
+        self.loops
+            .iter()
+            .map(|(&(tail, head), &iterations)| Loop {
+                head,
+                tail,
+                iterations,
+                executed: (head..=tail).map(|addr| self.count(addr)).sum(),
+            })
+            .sorted_by_key(|l| (usize::MAX - l.iterations, usize::MAX - l.executed, l.head))
+            .collect()
+    }
+
+    // Runs of instructions that never ran but can be reached from ones that
+    // did, such as the side of a branch that was never taken.
+    pub fn unexecuted(&self) -> Vec<Range<usize>> {
+        let ran = (0..self.counts.len()).filter(|&addr| self.count(addr) > 0);
+        let cfg = cfg::analyse_from(&self.image, ran);
+        let lines = cfg
+            .blocks()
+            .flat_map(|block| &block.lines)
+            .filter(|line| matches!(line, Line::Insn { .. }))
+            .filter(|line| self.count(line.addr()) == 0)
+            .map(|line| line.addr()..line.addr() + line.size())
+            .sorted_by_key(|cells| cells.start);
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for cells in lines {
+            match ranges.last_mut() {
+                Some(last) if last.end == cells.start => last.end = cells.end,
+                _ => ranges.push(cells),
+            }
+        }
+        ranges
+    }
+
+    // Runs of adjacent cells written to, in address order.
+    pub fn written(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        for (&addr, &writes) in self.writes.iter().sorted() {
+            match regions.last_mut() {
+                Some(last) if last.cells.end == addr => {
+                    last.cells.end += 1;
+                    last.writes += writes;
+                }
+                _ => regions.push(Region {
+                    cells: addr..addr + 1,
+                    writes,
+                }),
+            }
+        }
+        regions
+    }
+
+    // Each call stack seen, as the functions on it from the outermost, and how
+    // many instructions ran in it.
+    pub fn collapsed(&self) -> HashMap<Vec<usize>, usize> {
+        let mut stacks = self.stacks.clone();
+        if self.run > 0 {
+            *stacks.entry(self.stack()).or_default() += self.run;
+        }
+        stacks
+    }
+
+    // Writes the collapsed stacks for a flame graph.
+    pub fn write_collapsed<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        for (stack, count) in self.collapsed().into_iter().sorted() {
+            let names = stack.iter().map(|entry| format!(";fn@{}", entry)).join("");
+            writeln!(out, "main{} {}", names, count)?;
+        }
+        Ok(())
+    }
+
+    // Writes the disassembly of the starting memory with how many times each
+    // instruction ran beside it, and how many times each cell was written
+    // after it. A line that would hide an instruction that ran is shown as
+    // data, so the listing stays in step with what actually executed.
+    pub fn write_listing<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        let mut addr = 0;
+        while let Some(mut line) = Line::at(&self.image, addr) {
+            if (addr + 1..addr + line.size()).any(|a| self.count(a) > 0) {
+                line = Line::Data {
+                    addr,
+                    value: self.image[addr],
+                };
+            }
+            let count = match self.count(addr) {
+                0 => String::new(),
+                count => count.to_string(),
+            };
+            let writes = (addr..addr + line.size())
+                .filter_map(|a| self.writes.get(&a).map(|w| (a, w)))
+                .map(|(a, w)| format!("[{}] written {}", a, w))
+                .join(", ");
+            match writes.as_str() {
+                "" => writeln!(out, "{:>10} {}", count, line)?,
+                _ => writeln!(out, "{:>10} {} ; {}", count, line, writes)?,
+            }
+            addr += line.size();
+        }
+        Ok(())
+    }
+
+    fn stack(&self) -> Vec<usize> {
+        self.frames.iter().map(|&(entry, _)| entry).collect()
+    }
+
+    // Moves to a new call stack, filing what ran in the old one.
+    fn switch(&mut self, call: Option<usize>) {
+        if self.run > 0 {
+            *self.stacks.entry(self.stack()).or_default() += self.run;
+            self.run = 0;
+        }
+        match call {
+            Some(entry) => self.frames.push((entry, self.relbase)),
+            None => {
+                while matches!(self.frames.last(), Some(&(_, base)) if base >= self.relbase) {
+                    self.frames.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<W: Word> Tracer<W> for Profile {
+    fn trace(&mut self, step: &Step<W>) {
+        if self.counts.len() <= step.addr {
+            self.counts.resize(step.addr + 1, 0);
+        }
+        self.counts[step.addr] += 1;
+        self.run += 1;
+        if let Some((addr, val)) = &step.write {
+            *self.writes.entry(*addr).or_default() += 1;
+            if step.modes.last() == Some(&Mode::Relative) {
+                self.pushed.extend(val.to_int());
+            }
+        }
+        if let Some(relbase) = step.relbase {
+            self.relbase = relbase;
+        }
+        let size = step.params.len() + 1;
+        if step.next == step.addr + size || step.insn == Insn::End {
+            return;
+        }
+        let pushed = mem::take(&mut self.pushed);
+        if step.modes.get(1) == Some(&Mode::Relative) {
+            self.switch(None);
+        } else if pushed.contains(&((step.addr + size) as Int)) {
+            self.switch(Some(step.next));
+        } else if step.next <= step.addr {
+            *self.loops.entry((step.addr, step.next)).or_default() += 1;
+        }
+    }
+}
+
+impl<W: Word> VM<W> {
+    // Starts profiling into a new profile, in place of any tracer.
+    pub fn profile(&mut self) -> Arc<Mutex<Profile>> {
+        let image = self
+            .mem
+            .words()
+            .iter()
+            .map(|w| w.to_int().unwrap_or_default())
+            .collect();
+        let profile = Arc::new(Mutex::new(Profile::new(image, self.relbase)));
+        self.set_tracer(profile.clone());
+        profile
+    }
+}
+
+// A summary of where the time went: the hottest loops, the code that never
+// ran, and the memory written to.
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let executed = self.executed();
+        writeln!(f, "{} instructions executed", executed)?;
+        let loops = self.hot_loops();
+        if !loops.is_empty() {
+            writeln!(f, "hottest loops:")?;
+        }
+        for l in loops.iter().take(HOT_LOOPS) {
+            writeln!(
+                f,
+                "  {:>6}..={:<6} {:>10} iterations {:>12} instructions ({:.1}%)",
+                l.head,
+                l.tail,
+                l.iterations,
+                l.executed,
+                100.0 * l.executed as f64 / executed as f64
+            )?;
+        }
+        let unexecuted = self.unexecuted();
+        if !unexecuted.is_empty() {
+            writeln!(f, "never executed:")?;
+        }
+        for cells in unexecuted {
+            writeln!(f, "  {:>6}..{}", cells.start, cells.end)?;
+        }
+        let written = self.written();
+        if !written.is_empty() {
+            writeln!(f, "written:")?;
+        }
+        for region in written {
+            let place = if region.cells.start >= self.image.len() {
+                " past the program"
+            } else if region.cells.end > self.image.len() {
+                " over the end of the program"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "  {:>6}..{:<6} {:>10} writes{}",
+                region.cells.start, region.cells.end, region.writes, place
+            )?;
+        }
+        Ok(())
+    }
+}
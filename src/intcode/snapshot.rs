@@ -0,0 +1,171 @@
+use std::io::{self, BufRead, Write};
+use std::iter::FromIterator;
+
+use itertools::Itertools;
+
+use crate::intcode::memory::Memory;
+use crate::intcode::word::Word;
+use crate::intcode::VM;
+
+// Snapshots are plain text, one field per line:
+//
+//   intcode-snapshot 1
+//   ip 1047
+//   rb 3112
+//   input 110,111,114,116,104,10
+//   executed 5170
+//   fuel 1000
+//   paged 3100
+//   limit 1000000
+//   len 3200
+//   mem 0 109,4817,21101,0,1,-2
+//   mem 3100 5,0,0,17
+//
+// `paged` gives how many words at the start of memory are held outside the
+// pages, which is where the program is, and `halted` on a line of its own
+// marks a VM that has already run the END it's at. Memory is written as runs
+// of words, split wherever there's a long enough stretch of zeros, so sparse
+// memory stays small. Loading refuses memory that wouldn't fit under the limit
+// or that dense memory couldn't allocate.
+//
+// Breakpoints, watchpoints, the tracer, the undo log, the decode cache and the
+// per-instruction counts and history aren't part of a snapshot.
+
+const HEADER: &str = "intcode-snapshot 1";
+
+// Fewer zeros than this in a row are kept inside a run of memory.
+const MAX_GAP: usize = 16;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    IOError(io::Error),
+    BadHeader(String),
+    UnknownField(usize, String),
+    BadValue(usize, String),
+    MissingIp,
+}
+
+impl<W: Word> VM<W> {
+    pub fn save<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "ip {}", self.insn)?;
+        writeln!(out, "rb {}", self.relbase)?;
+        if !self.inbuf.is_empty() {
+            writeln!(out, "input {}", self.inbuf.iter().join(","))?;
+        }
+        writeln!(out, "executed {}", self.executed)?;
+        if let Some(fuel) = self.fuel {
+            writeln!(out, "fuel {}", fuel)?;
+        }
+        if self.halted {
+            writeln!(out, "halted")?;
+        }
+        if self.mem.is_paged() {
+            writeln!(out, "paged {}", self.mem.words().len())?;
+        }
+        if let Some(limit) = self.mem.limit() {
+            writeln!(out, "limit {}", limit)?;
+        }
+        writeln!(out, "len {}", self.mem.len())?;
+
+        let mut runs: Vec<(usize, Vec<W>)> = Vec::new();
+        for (addr, w) in self.mem.cells().filter(|(_, w)| !w.is_zero()) {
+            match runs.last_mut() {
+                Some((start, run)) if addr - (*start + run.len()) < MAX_GAP => {
+                    run.resize(addr - *start, W::zero());
+                    run.push(w.clone());
+                }
+                _ => runs.push((addr, vec![w.clone()])),
+            }
+        }
+        for (start, run) in runs {
+            writeln!(out, "mem {} {}", start, run.iter().join(","))?;
+        }
+        Ok(())
+    }
+
+    pub fn load<I: BufRead>(input: I) -> Result<Self, SnapshotError> {
+        let mut lines = input.lines();
+        match lines.next() {
+            Some(Ok(line)) if line.trim() == HEADER => (),
+            Some(Ok(line)) => return Err(SnapshotError::BadHeader(line)),
+            Some(Err(e)) => return Err(SnapshotError::IOError(e)),
+            None => return Err(SnapshotError::BadHeader(String::new())),
+        }
+
+        // memory is built once everything about it is known, each part
+        // kept with the line it came from
+        let mut vm = VM::with_memory(Memory::dense(Vec::new()));
+        let mut insn = None;
+        let mut paged = None;
+        let mut limit = None;
+        let mut len = None;
+        let mut runs = Vec::new();
+        for (i, line) in (2..).zip(lines) {
+            let line = line.map_err(SnapshotError::IOError)?;
+            let bad = || SnapshotError::BadValue(i, line.clone());
+            let (field, value) = match line.trim().find(' ') {
+                Some(n) => (&line.trim()[..n], line.trim()[n..].trim()),
+                None => (line.trim(), ""),
+            };
+            match field {
+                "" => (),
+                "ip" => insn = Some(value.parse().map_err(|_| bad())?),
+                "rb" => vm.relbase = value.parse().map_err(|_| bad())?,
+                "input" => vm.inbuf = words(value).ok_or_else(bad)?,
+                "executed" => vm.executed = value.parse().map_err(|_| bad())?,
+                "fuel" => vm.fuel = Some(value.parse().map_err(|_| bad())?),
+                "halted" => vm.halted = true,
+                // older snapshots don't say how many words aren't paged
+                "paged" if value.is_empty() => paged = Some((None, i, line)),
+                "paged" => paged = Some((Some(value.parse().map_err(|_| bad())?), i, line)),
+                "limit" => limit = Some(value.parse().map_err(|_| bad())?),
+                "len" => len = Some((value.parse().map_err(|_| bad())?, i, line)),
+                "mem" => {
+                    let (start, run) = value.split_once(' ').ok_or_else(bad)?;
+                    let start: usize = start.parse().map_err(|_| bad())?;
+                    let run: Vec<W> = words(run).ok_or_else(bad)?;
+                    runs.push((start, run, i, line));
+                }
+                _ => return Err(SnapshotError::UnknownField(i, line)),
+            }
+        }
+
+        if let Some(limit) = limit {
+            vm.mem = vm.mem.with_limit(limit);
+        }
+        if let Some((words, i, line)) = paged {
+            // without a count, the run the program is in stays out of the pages
+            let words = words.unwrap_or_else(|| match runs.first() {
+                Some((0, run, _, _)) => run.len(),
+                _ => 0,
+            });
+            if !vm.mem.make_room(words) {
+                return Err(SnapshotError::BadValue(i, line));
+            }
+            vm.mem.grow(words);
+            vm.mem = vm.mem.into_paged();
+        }
+        for (start, run, i, line) in runs {
+            match start.checked_add(run.len()) {
+                Some(end) if vm.mem.make_room(end) => (),
+                _ => return Err(SnapshotError::BadValue(i, line)),
+            }
+            for (addr, w) in (start..).zip(run) {
+                vm.mem[addr] = w;
+            }
+        }
+        if let Some((len, i, line)) = len {
+            if !vm.mem.make_room(len) {
+                return Err(SnapshotError::BadValue(i, line));
+            }
+            vm.mem.grow(len);
+        }
+        vm.insn = insn.ok_or(SnapshotError::MissingIp)?;
+        Ok(vm)
+    }
+}
+
+fn words<C: FromIterator<W>, W: Word>(s: &str) -> Option<C> {
+    s.split(',').map(|w| w.trim().parse().ok()).collect()
+}
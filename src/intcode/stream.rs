@@ -0,0 +1,110 @@
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use futures::{Sink, Stream};
+
+use crate::intcode::word::Word;
+use crate::intcode::{ExecError, Int, State, VM};
+
+// A VM as a stream of its outputs and a sink for its inputs, so VMs can be
+// wired together with `forward`, `split` and friends and run on an executor.
+//
+// The stream ends when the program finishes, or after yielding the error it
+// failed with. While the program waits for input the stream is pending until
+// something is sent to the sink, and when it runs out of fuel it's pending
+// until it's refuelled with `set_fuel`. Breakpoints and watchpoints are run
+// through.
+pub struct VMStream<W = Int> {
+    vm: VM<W>,
+    waker: Option<Waker>,
+    done: bool,
+}
+
+// Nothing is ever pinned in place, the VM is only reached through `&mut`.
+impl<W> Unpin for VMStream<W> {}
+
+impl<W: Word> VM<W> {
+    pub fn into_stream(self) -> VMStream<W> {
+        VMStream {
+            vm: self,
+            waker: None,
+            done: false,
+        }
+    }
+}
+
+impl<W: Word> VMStream<W> {
+    pub fn get_ref(&self) -> &VM<W> {
+        &self.vm
+    }
+
+    pub fn get_mut(&mut self) -> &mut VM<W> {
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> VM<W> {
+        self.vm
+    }
+
+    // Refuels the VM, waking the stream if it stopped for lack of fuel.
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.vm.set_fuel(fuel);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<W: Word> Stream for VMStream<W> {
+    type Item = Result<W, ExecError<W>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        loop {
+            match self.vm.next_state() {
+                Ok(State::Outputting(w)) => return Poll::Ready(Some(Ok(w))),
+                Ok(State::AwaitingInput) | Ok(State::OutOfFuel) => {
+                    self.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                Ok(State::Finished) => {
+                    self.done = true;
+                    return Poll::Ready(None);
+                }
+                Ok(State::Breakpoint(_)) | Ok(State::Watch(_, _)) => (),
+                Err(e) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+// Input is buffered without bound, so the sink is always ready. Its error type
+// matches the stream's, letting one VM's outputs be forwarded into another.
+impl<W: Word> Sink<W> for VMStream<W> {
+    type Error = ExecError<W>;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: W) -> Result<(), Self::Error> {
+        self.vm.input(item);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
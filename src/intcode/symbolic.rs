@@ -0,0 +1,705 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use itertools::Itertools;
+
+use crate::intcode::trace::{Step, Tracer};
+use crate::intcode::{ExecError, Insn, Int, Mode, Program, State, VM};
+
+// Concolic execution: the program runs as normal on concrete values, while a
+// tracer follows along working out the value of each cell as an expression of
+// the variables, which are chosen cells of the program and inputs. Every branch
+// on a value that depends on them adds a constraint to the path taken.
+//
+// Expressions are kept linear. Anything else - the product of two variables, a
+// comparison used as a number, a computed address - is replaced by its value
+// on that run, so values solved for from the constraints are only a guess until
+// a run confirms them, which is what `solve` does.
+
+// How many runs `solve` makes, and how many nodes the solver searches, before
+// giving up.
+const RUNS: usize = 64;
+const NODES: usize = 100_000;
+
+// How many times the solver tightens the bounds before it starts splitting
+// them instead.
+const PASSES: usize = 64;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Linear {
+    terms: BTreeMap<usize, Int>,
+    constant: Int,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Linear(Linear),
+    // 1 if the constraint holds and 0 if not, as from LESS_THAN and EQUALS.
+    Flag(Constraint),
+}
+
+// The expression compared with zero.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Constraint {
+    pub expr: Linear,
+    pub rel: Rel,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Rel {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+}
+
+pub struct Symbolic {
+    program: Program,
+    vars: Vec<Var>,
+    fuel: Option<usize>,
+}
+
+struct Var {
+    source: Source,
+    range: RangeInclusive<Int>,
+}
+
+enum Source {
+    Cell(usize),
+    Input,
+}
+
+pub struct Run {
+    values: Vec<Int>,
+    state: Result<State, ExecError>,
+    vm: VM,
+    cells: HashMap<usize, Expr>,
+    path: Vec<Constraint>,
+    outputs: Vec<Expr>,
+}
+
+impl Symbolic {
+    pub fn new(program: &Program) -> Self {
+        Symbolic {
+            program: program.clone(),
+            vars: Vec::new(),
+            fuel: None,
+        }
+    }
+
+    // Each variable is numbered in the order it's added, from x0.
+    pub fn with_cell(mut self, addr: usize, range: RangeInclusive<Int>) -> Self {
+        self.vars.push(Var {
+            source: Source::Cell(addr),
+            range,
+        });
+        self
+    }
+
+    // Makes the next input the program reads a variable.
+    pub fn with_input(mut self, range: RangeInclusive<Int>) -> Self {
+        self.vars.push(Var {
+            source: Source::Input,
+            range,
+        });
+        self
+    }
+
+    pub fn with_fuel(mut self, fuel: usize) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    // Runs the program with a value for each variable, until it finishes, wants
+    // more input than it's been given, fails or runs out of fuel.
+    pub fn run(&self, values: &[Int]) -> Run {
+        assert_eq!(values.len(), self.vars.len(), "one value per variable");
+        let mut vm = VM::of(&self.program);
+        vm.set_fuel(self.fuel);
+        let mut shadow = Shadow::default();
+        for (var, (v, &value)) in self.vars.iter().zip(values).enumerate() {
+            match v.source {
+                Source::Cell(addr) => {
+                    vm.mem[addr] = value;
+                    shadow.cells.insert(addr, Expr::var(var));
+                }
+                Source::Input => {
+                    vm.input(value);
+                    shadow.inputs.push_back(var);
+                }
+            }
+        }
+
+        let shadow = Arc::new(Mutex::new(shadow));
+        vm.set_tracer(shadow.clone());
+        let state = loop {
+            match vm.next_state() {
+                Ok(State::Outputting(_)) => (),
+                state => break state,
+            }
+        };
+        vm.clear_tracer();
+        let shadow = mem::take(&mut *shadow.lock().unwrap());
+        Run {
+            values: values.to_vec(),
+            state,
+            vm,
+            cells: shadow.cells,
+            path: shadow.path,
+            outputs: shadow.outputs,
+        }
+    }
+
+    // Looks for values of the variables that make the constraints from `goal`
+    // hold at the end of a run, starting from `seed`. The next values to try
+    // come from solving a run's path together with its goal, and when that's
+    // impossible, from flipping branches along the path to try others.
+    pub fn solve<F>(&self, seed: &[Int], goal: F) -> Option<Vec<Int>>
+    where
+        F: Fn(&Run) -> Vec<Constraint>,
+    {
+        let bounds = self.vars.iter().map(|v| v.range.clone()).collect_vec();
+        let mut queue = VecDeque::new();
+        let mut tried = HashSet::new();
+        queue.push_back(seed.to_vec());
+        for _ in 0..RUNS {
+            let values = queue.pop_front()?;
+            if !tried.insert(values.clone()) {
+                continue;
+            }
+            let run = self.run(&values);
+            let goal = goal(&run);
+            if goal.iter().all(|c| c.holds(&values)) {
+                return Some(values);
+            }
+            let mut constraints = run.path.clone();
+            constraints.extend(goal);
+            if let Some(next) = satisfy(&constraints, &bounds, &values) {
+                queue.push_front(next);
+            }
+            for i in (0..run.path.len()).rev() {
+                let mut flipped = run.path[..i].to_vec();
+                flipped.push(run.path[i].negate());
+                if let Some(next) = satisfy(&flipped, &bounds, &values) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Run {
+    pub fn values(&self) -> &[Int] {
+        &self.values
+    }
+
+    pub fn state(&self) -> &Result<State, ExecError> {
+        &self.state
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    // What's in a cell at the end of the run.
+    pub fn cell(&self, addr: usize) -> Expr {
+        match self.cells.get(&addr) {
+            Some(expr) => expr.clone(),
+            None => Expr::Linear(Linear::of(self.vm.mem[addr])),
+        }
+    }
+
+    // The constraints the branches taken put on the variables, in order.
+    pub fn path(&self) -> &[Constraint] {
+        &self.path
+    }
+
+    pub fn outputs(&self) -> &[Expr] {
+        &self.outputs
+    }
+}
+
+// Follows a run, keeping the expressions for cells that depend on variables.
+#[derive(Default)]
+struct Shadow {
+    cells: HashMap<usize, Expr>,
+    inputs: VecDeque<usize>,
+    relbase: Int,
+    path: Vec<Constraint>,
+    outputs: Vec<Expr>,
+}
+
+impl Shadow {
+    fn operand(&self, step: &Step, i: usize) -> Expr {
+        let cell = match step.modes[i] {
+            Mode::Immediate => Some((step.addr + 1 + i) as Int),
+            Mode::Position => Some(step.params[i]),
+            Mode::Relative => self.relbase.checked_add(step.params[i]),
+        };
+        match cell.and_then(|cell| self.cells.get(&(cell as usize))) {
+            Some(expr) => expr.clone(),
+            None => Expr::Linear(Linear::of(step.args[i])),
+        }
+    }
+
+    // The operand as a linear expression, going concrete if it isn't one.
+    fn linear(&self, step: &Step, i: usize) -> Linear {
+        match self.operand(step, i) {
+            Expr::Linear(linear) => linear,
+            Expr::Flag(_) => Linear::of(step.args[i]),
+        }
+    }
+}
+
+impl Tracer for Shadow {
+    fn trace(&mut self, step: &Step) {
+        let result = match step.insn {
+            Insn::Add => self
+                .linear(step, 0)
+                .add(&self.linear(step, 1))
+                .map(Expr::Linear),
+            Insn::Mul => {
+                let (a, b) = (self.linear(step, 0), self.linear(step, 1));
+                match (a.value(), b.value()) {
+                    (Some(k), _) => b.scale(k),
+                    (_, Some(k)) => a.scale(k),
+                    _ => None,
+                }
+                .map(Expr::Linear)
+            }
+            Insn::LessThan => compare(self.operand(step, 0), self.operand(step, 1), Rel::Lt),
+            Insn::Equals => compare(self.operand(step, 0), self.operand(step, 1), Rel::Eq),
+            Insn::Input => self.inputs.pop_front().map(Expr::var),
+            Insn::Output => {
+                let out = self.operand(step, 0);
+                self.outputs.push(out);
+                None
+            }
+            Insn::JumpIfTrue | Insn::JumpIfFalse => {
+                let taken = step.args[0] != 0;
+                let constraint = match self.operand(step, 0) {
+                    Expr::Flag(c) if taken => Some(c),
+                    Expr::Flag(c) => Some(c.negate()),
+                    Expr::Linear(l) if l.value().is_some() => None,
+                    Expr::Linear(expr) => Some(Constraint {
+                        expr,
+                        rel: if taken { Rel::Ne } else { Rel::Eq },
+                    }),
+                };
+                self.path.extend(constraint);
+                None
+            }
+            Insn::SetBase | Insn::End | Insn::Ext(_) => None,
+        };
+        if let Some((addr, _)) = step.write {
+            match result {
+                Some(expr) if expr.value().is_none() => self.cells.insert(addr, expr),
+                _ => self.cells.remove(&addr),
+            };
+        }
+        if let Some(relbase) = step.relbase {
+            self.relbase = relbase;
+        }
+    }
+}
+
+// The flag for `a rel b`, or `None` if it doesn't depend on any variables.
+fn compare(a: Expr, b: Expr, rel: Rel) -> Option<Expr> {
+    let flag = match (&a, &b, rel) {
+        (Expr::Flag(c), Expr::Linear(l), Rel::Eq) | (Expr::Linear(l), Expr::Flag(c), Rel::Eq) => {
+            match l.value() {
+                Some(1) => Some(c.clone()),
+                Some(0) => Some(c.negate()),
+                _ => None,
+            }
+        }
+        (Expr::Linear(a), Expr::Linear(b), _) => a
+            .add(&b.scale(-1)?)
+            .filter(|expr| expr.value().is_none())
+            .map(|expr| Constraint { expr, rel }),
+        _ => None,
+    };
+    flag.map(Expr::Flag)
+}
+
+impl Linear {
+    pub fn of(value: Int) -> Self {
+        Linear {
+            terms: BTreeMap::new(),
+            constant: value,
+        }
+    }
+
+    pub fn var(var: usize) -> Self {
+        Linear {
+            terms: vec![(var, 1)].into_iter().collect(),
+            constant: 0,
+        }
+    }
+
+    // The value, if there are no variables.
+    pub fn value(&self) -> Option<Int> {
+        if self.terms.is_empty() {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    // Each variable with its coefficient.
+    pub fn terms(&self) -> impl Iterator<Item = (usize, Int)> + '_ {
+        self.terms.iter().map(|(&var, &k)| (var, k))
+    }
+
+    pub fn constant(&self) -> Int {
+        self.constant
+    }
+
+    pub fn eval(&self, values: &[Int]) -> Option<Int> {
+        self.terms().try_fold(self.constant, |sum, (var, k)| {
+            sum.checked_add(k.checked_mul(*values.get(var)?)?)
+        })
+    }
+
+    // The sum, or `None` if it overflows, as do `scale` and `eval`.
+    pub fn add(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        for (var, k) in other.terms() {
+            let term = sum.terms.entry(var).or_insert(0);
+            *term = term.checked_add(k)?;
+            if *term == 0 {
+                sum.terms.remove(&var);
+            }
+        }
+        Some(sum)
+    }
+
+    pub fn scale(&self, k: Int) -> Option<Linear> {
+        if k == 0 {
+            return Some(Linear::of(0));
+        }
+        Some(Linear {
+            terms: self
+                .terms()
+                .map(|(var, c)| Some((var, c.checked_mul(k)?)))
+                .collect::<Option<_>>()?,
+            constant: self.constant.checked_mul(k)?,
+        })
+    }
+}
+
+impl Expr {
+    pub fn var(var: usize) -> Self {
+        Expr::Linear(Linear::var(var))
+    }
+
+    pub fn value(&self) -> Option<Int> {
+        match self {
+            Expr::Linear(linear) => linear.value(),
+            Expr::Flag(_) => None,
+        }
+    }
+
+    pub fn equals(&self, value: Int) -> Constraint {
+        match self {
+            Expr::Linear(linear) => Constraint::of(linear, value, Rel::Eq),
+            Expr::Flag(c) if value == 1 => c.clone(),
+            Expr::Flag(c) if value == 0 => c.negate(),
+            Expr::Flag(_) => Constraint::never(),
+        }
+    }
+
+    pub fn less_than(&self, value: Int) -> Constraint {
+        match self {
+            Expr::Linear(linear) => Constraint::of(linear, value, Rel::Lt),
+            Expr::Flag(c) if value == 1 => c.negate(),
+            Expr::Flag(_) if value > 1 => Constraint::never().negate(),
+            Expr::Flag(_) => Constraint::never(),
+        }
+    }
+}
+
+impl Constraint {
+    // `linear rel value`
+    fn of(linear: &Linear, value: Int, rel: Rel) -> Self {
+        match value.checked_neg().and_then(|v| linear.add(&Linear::of(v))) {
+            Some(expr) => Constraint { expr, rel },
+            None => Constraint::never(),
+        }
+    }
+
+    fn never() -> Self {
+        Constraint {
+            expr: Linear::of(1),
+            rel: Rel::Eq,
+        }
+    }
+
+    pub fn negate(&self) -> Self {
+        Constraint {
+            expr: self.expr.clone(),
+            rel: match self.rel {
+                Rel::Eq => Rel::Ne,
+                Rel::Ne => Rel::Eq,
+                Rel::Lt => Rel::Ge,
+                Rel::Ge => Rel::Lt,
+            },
+        }
+    }
+
+    pub fn holds(&self, values: &[Int]) -> bool {
+        match self.expr.eval(values) {
+            Some(v) => match self.rel {
+                Rel::Eq => v == 0,
+                Rel::Ne => v != 0,
+                Rel::Lt => v < 0,
+                Rel::Ge => v >= 0,
+            },
+            None => false,
+        }
+    }
+}
+
+// Finds values within the bounds that satisfy all the constraints, as close to
+// `near` as it happens to manage, by narrowing the bounds of each variable as
+// far as the constraints allow and splitting them in two when they won't go
+// any further.
+pub fn satisfy(
+    constraints: &[Constraint],
+    bounds: &[RangeInclusive<Int>],
+    near: &[Int],
+) -> Option<Vec<Int>> {
+    let domains = bounds
+        .iter()
+        .map(|range| (*range.start() as i128, *range.end() as i128))
+        .collect_vec();
+    let mut nodes = 0;
+    search(constraints, domains, near, &mut nodes)
+}
+
+fn search(
+    constraints: &[Constraint],
+    mut domains: Vec<(i128, i128)>,
+    near: &[Int],
+    nodes: &mut usize,
+) -> Option<Vec<Int>> {
+    *nodes += 1;
+    if *nodes > NODES || !propagate(constraints, &mut domains) {
+        return None;
+    }
+    let var = (0..domains.len())
+        .filter(|&var| domains[var].0 < domains[var].1)
+        .min_by_key(|&var| domains[var].1 - domains[var].0);
+    let var = match var {
+        Some(var) => var,
+        None => {
+            let values = domains.iter().map(|&(v, _)| v as Int).collect_vec();
+            return Some(values).filter(|values| constraints.iter().all(|c| c.holds(values)));
+        }
+    };
+    let (lo, hi) = domains[var];
+    let mid = lo + (hi - lo) / 2;
+    let mut halves = [(lo, mid), (mid + 1, hi)];
+    if near.get(var).is_some_and(|&v| v as i128 > mid) {
+        halves.swap(0, 1);
+    }
+    for &half in &halves {
+        let mut domains = domains.clone();
+        domains[var] = half;
+        if let Some(values) = search(constraints, domains, near, nodes) {
+            return Some(values);
+        }
+    }
+    None
+}
+
+// Tightens the bounds to what the constraints allow, and returns false if
+// they can't all hold.
+fn propagate(constraints: &[Constraint], domains: &mut [(i128, i128)]) -> bool {
+    for _ in 0..PASSES {
+        let mut changed = false;
+        for c in constraints {
+            match tighten(c, domains) {
+                Some(true) => changed = true,
+                Some(false) => (),
+                None => return false,
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    true
+}
+
+// Narrows the bounds for one constraint, returning whether they changed, or
+// `None` if it can't hold. The sum is a*x + rest for each term a*x, so each
+// term's bounds follow from what's needed of the sum and the bounds of the
+// rest.
+fn tighten(c: &Constraint, domains: &mut [(i128, i128)]) -> Option<bool> {
+    let terms = c
+        .expr
+        .terms()
+        .map(|(var, k)| (var, k as i128))
+        .collect_vec();
+    let range = |domains: &[(i128, i128)], var: usize, k: i128| {
+        let (lo, hi) = domains[var];
+        let (a, b) = (k.checked_mul(lo)?, k.checked_mul(hi)?);
+        Some((a.min(b), a.max(b)))
+    };
+    let sum = |domains: &[(i128, i128)]| {
+        terms.iter().try_fold(
+            (c.expr.constant as i128, c.expr.constant as i128),
+            |(lo, hi), &(var, k)| {
+                let (a, b) = range(domains, var, k)?;
+                Some((lo.checked_add(a)?, hi.checked_add(b)?))
+            },
+        )
+    };
+    let (min, max) = match sum(domains) {
+        Some(bounds) => bounds,
+        // too big to reason about, so leave it to the final check
+        None => return Some(false),
+    };
+    let (need_lo, need_hi) = match c.rel {
+        Rel::Eq => (0, 0),
+        Rel::Lt => (min.min(-1), -1),
+        Rel::Ge => (0, max.max(0)),
+        Rel::Ne => return exclude(c, &terms, domains, min, max),
+    };
+    if max < need_lo || min > need_hi {
+        return None;
+    }
+
+    let mut changed = false;
+    for &(var, k) in &terms {
+        let (a, b) = range(domains, var, k)?;
+        // k*x is between these
+        let (lo, hi) = (need_lo - (max - b), need_hi - (min - a));
+        let (lo, hi) = if k > 0 {
+            (ceil_div(lo, k), floor_div(hi, k))
+        } else {
+            (ceil_div(hi, k), floor_div(lo, k))
+        };
+        let domain = &mut domains[var];
+        if lo > domain.0 || hi < domain.1 {
+            *domain = (domain.0.max(lo), domain.1.min(hi));
+            if domain.0 > domain.1 {
+                return None;
+            }
+            changed = true;
+        }
+    }
+    Some(changed)
+}
+
+// A sum that mustn't be zero can only rule out the end of a variable's bounds,
+// once every other variable is fixed.
+fn exclude(
+    c: &Constraint,
+    terms: &[(usize, i128)],
+    domains: &mut [(i128, i128)],
+    min: i128,
+    max: i128,
+) -> Option<bool> {
+    if min == max {
+        return if min == 0 { None } else { Some(false) };
+    }
+    let free = terms
+        .iter()
+        .filter(|&&(var, _)| domains[var].0 < domains[var].1)
+        .collect_vec();
+    let (var, k) = match free[..] {
+        [&(var, k)] => (var, k),
+        _ => return Some(false),
+    };
+    let rest = terms
+        .iter()
+        .filter(|&&(v, _)| v != var)
+        .try_fold(c.expr.constant as i128, |sum, &(v, k)| {
+            sum.checked_add(k.checked_mul(domains[v].0)?)
+        });
+    let rest = match rest {
+        Some(rest) => rest,
+        None => return Some(false),
+    };
+    if rest % k != 0 {
+        return Some(false);
+    }
+    let x = -rest / k;
+    let domain = &mut domains[var];
+    if x == domain.0 {
+        domain.0 += 1;
+    } else if x == domain.1 {
+        domain.1 -= 1;
+    } else {
+        return Some(false);
+    }
+    Some(true)
+}
+
+fn floor_div(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn ceil_div(a: i128, b: i128) -> i128 {
+    -floor_div(-a, b)
+}
+
+impl Display for Linear {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, (var, k)) in self.terms().enumerate() {
+            let sign = match (i, k < 0) {
+                (0, true) => "-",
+                (0, false) => "",
+                (_, true) => " - ",
+                (_, false) => " + ",
+            };
+            match k.unsigned_abs() {
+                1 => write!(f, "{}x{}", sign, var)?,
+                k => write!(f, "{}{}*x{}", sign, k, var)?,
+            }
+        }
+        match (self.terms.is_empty(), self.constant) {
+            (true, c) => write!(f, "{}", c),
+            (false, 0) => Ok(()),
+            (false, c) if c < 0 => write!(f, " - {}", c.unsigned_abs()),
+            (false, c) => write!(f, " + {}", c),
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Linear(linear) => Display::fmt(linear, f),
+            Expr::Flag(c) => write!(f, "[{}]", c),
+        }
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let lhs = Linear {
+            terms: self.expr.terms.clone(),
+            constant: 0,
+        };
+        let rel = match self.rel {
+            Rel::Eq => "==",
+            Rel::Ne => "!=",
+            Rel::Lt => "<",
+            Rel::Ge => ">=",
+        };
+        write!(f, "{} {} {}", lhs, rel, -(self.expr.constant as i128))
+    }
+}
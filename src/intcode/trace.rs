@@ -0,0 +1,66 @@
+use std::fmt::{self, Display, Formatter};
+use std::iter;
+
+use itertools::Itertools;
+
+use crate::intcode::disasm::Operand;
+use crate::intcode::word::Word;
+use crate::intcode::{Insn, Int, Mode};
+
+// One executed instruction. `params` are the raw operand words and `args` the
+// values read through them; the written cell, if any, is in `write`.
+#[derive(Clone, Debug)]
+pub struct Step<'a, W = Int> {
+    pub addr: usize,
+    pub insn: Insn,
+    pub modes: &'a [Mode],
+    pub params: &'a [W],
+    pub args: &'a [W],
+    pub write: Option<(usize, W)>,
+    pub relbase: Option<Int>,
+    pub next: usize,
+}
+
+pub trait Tracer<W = Int> {
+    fn trace(&mut self, step: &Step<W>);
+}
+
+impl<W, F: FnMut(&Step<W>)> Tracer<W> for F {
+    fn trace(&mut self, step: &Step<W>) {
+        self(step)
+    }
+}
+
+impl<W: Word> Display for Step<'_, W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>6}: {:<13} {}",
+            self.addr,
+            self.insn.mnemonic(),
+            self.modes
+                .iter()
+                .zip(self.params)
+                .map(|(&mode, value)| Operand {
+                    mode,
+                    value: value.clone(),
+                })
+                .zip(self.args.iter().map(Some).chain(iter::repeat(None)))
+                .map(|(op, arg)| match (op.mode, arg) {
+                    (Mode::Immediate, _) | (_, None) => format!("{}", op),
+                    (_, Some(arg)) => format!("{}={}", op, arg),
+                })
+                .join(", ")
+        )?;
+        if let Some((addr, val)) = &self.write {
+            write!(f, " -> [{}]={}", addr, val)?;
+        }
+        if let Some(base) = self.relbase {
+            write!(f, " rb={}", base)?;
+        }
+        if self.next != self.addr + self.params.len() + 1 && self.insn != Insn::End {
+            write!(f, " jump {}", self.next)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use std::error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::intcode::trace::{Step, Tracer};
+use crate::intcode::word::Word;
+use crate::intcode::{ExecError, Insn, Int, Program, State, VM};
+
+// A record of everything a VM read and wrote, each event tagged with how many
+// instructions had run when it happened, counting from when recording began.
+// Transcripts are plain text, one event per line, after any changes made to
+// the program's memory before it started:
+//
+//   intcode-transcript 1
+//   mem 0 2
+//   in 2 1
+//   out 96 72
+//
+// Replaying one against a VM in the state the recording started from, or on
+// the program as loaded with the changes made again, feeds it the same input
+// and checks it produces the same output at the same points.
+
+const HEADER: &str = "intcode-transcript 1";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event<W = Int> {
+    Input(W),
+    Output(W),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry<W = Int> {
+    pub count: usize,
+    pub event: Event<W>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Transcript<W = Int> {
+    // memory changed before the recording started, such as to pick a mode
+    patches: Vec<(usize, W)>,
+    entries: Vec<Entry<W>>,
+    count: usize,
+}
+
+#[derive(Debug)]
+pub enum TranscriptError {
+    IOError(io::Error),
+    BadHeader(String),
+    BadEvent(usize, String),
+}
+
+pub enum ReplayError<W = Int> {
+    // The VM output something other than what entry n says.
+    UnexpectedOutput(usize, Entry<W>, usize, W),
+    // The VM wanted input where entry n doesn't give it any.
+    UnexpectedInput(usize, Entry<W>, usize),
+    // The program finished or ran out of fuel with entries left over, starting
+    // with entry n.
+    UnexpectedEnd(usize),
+    Exec(ExecError<W>),
+}
+
+impl<W: Word> Transcript<W> {
+    pub fn new() -> Self {
+        Transcript {
+            patches: Vec::new(),
+            entries: Vec::new(),
+            count: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry<W>] {
+        &self.entries
+    }
+
+    pub fn patches(&self) -> &[(usize, W)] {
+        &self.patches
+    }
+
+    // Notes a change made to the program's memory before the recording began,
+    // for replaying to make again. `VM::record_from` finds them for itself.
+    pub fn patch(&mut self, addr: usize, w: W) {
+        self.patches.push((addr, w));
+    }
+
+    pub fn save<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        writeln!(out, "{}", HEADER)?;
+        for (addr, w) in &self.patches {
+            writeln!(out, "mem {} {}", addr, w)?;
+        }
+        for entry in &self.entries {
+            match &entry.event {
+                Event::Input(w) => writeln!(out, "in {} {}", entry.count, w)?,
+                Event::Output(w) => writeln!(out, "out {} {}", entry.count, w)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load<I: BufRead>(input: I) -> Result<Self, TranscriptError> {
+        let mut lines = input.lines();
+        match lines.next() {
+            Some(Ok(line)) if line.trim() == HEADER => (),
+            Some(Ok(line)) => return Err(TranscriptError::BadHeader(line)),
+            Some(Err(e)) => return Err(TranscriptError::IOError(e)),
+            None => return Err(TranscriptError::BadHeader(String::new())),
+        }
+
+        let mut transcript = Transcript::new();
+        for (i, line) in (2..).zip(lines) {
+            let line = line.map_err(TranscriptError::IOError)?;
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let entry = match fields[..] {
+                [] => continue,
+                [kind, count, value] => {
+                    let count = count.parse().ok();
+                    let value = value.parse().ok();
+                    match (kind, count, value) {
+                        ("mem", Some(addr), Some(w)) if transcript.entries.is_empty() => {
+                            transcript.patches.push((addr, w));
+                            continue;
+                        }
+                        ("in", Some(count), Some(w)) => Entry {
+                            count,
+                            event: Event::Input(w),
+                        },
+                        ("out", Some(count), Some(w)) => Entry {
+                            count,
+                            event: Event::Output(w),
+                        },
+                        _ => return Err(TranscriptError::BadEvent(i, line)),
+                    }
+                }
+                _ => return Err(TranscriptError::BadEvent(i, line)),
+            };
+            transcript.count = entry.count;
+            transcript.entries.push(entry);
+        }
+        Ok(transcript)
+    }
+}
+
+impl<W: Word> Tracer<W> for Transcript<W> {
+    fn trace(&mut self, step: &Step<W>) {
+        self.count += 1;
+        let event = match (step.insn, &step.write, step.args.first()) {
+            (Insn::Input, Some((_, w)), _) => Event::Input(w.clone()),
+            (Insn::Output, _, Some(w)) => Event::Output(w.clone()),
+            _ => return,
+        };
+        self.entries.push(Entry {
+            count: self.count,
+            event,
+        });
+    }
+}
+
+impl<W: Word> VM<W> {
+    // Starts recording into a new transcript. A VM only has the one tracer, so
+    // this replaces whatever was tracing it before, such as a profile.
+    pub fn record(&mut self) -> Arc<Mutex<Transcript<W>>> {
+        self.record_with(Transcript::new())
+    }
+
+    // Like `record`, but starts the transcript with every word of memory that
+    // differs from `program`, so replaying on the program as loaded makes the
+    // same changes first.
+    pub fn record_from(&mut self, program: &Program) -> Arc<Mutex<Transcript<W>>> {
+        let mut transcript = Transcript::new();
+        for (addr, w) in self.mem.cells() {
+            let original = program.instructions.get(addr).copied().unwrap_or(0);
+            if *w != W::from(original) {
+                transcript.patch(addr, w.clone());
+            }
+        }
+        self.record_with(transcript)
+    }
+
+    fn record_with(&mut self, transcript: Transcript<W>) -> Arc<Mutex<Transcript<W>>> {
+        let transcript = Arc::new(Mutex::new(transcript));
+        self.set_tracer(transcript.clone());
+        transcript
+    }
+
+    // Makes the transcript's changes to memory, then runs the VM through it,
+    // stopping after its last entry.
+    pub fn replay(&mut self, transcript: &Transcript<W>) -> Result<(), ReplayError<W>> {
+        for (addr, w) in &transcript.patches {
+            self.mem[*addr] = w.clone();
+        }
+        let start = self.executed;
+        for (i, entry) in transcript.entries.iter().enumerate() {
+            let state = loop {
+                match self.next_state().map_err(ReplayError::Exec)? {
+                    State::Breakpoint(_) | State::Watch(_, _) => (),
+                    state => break state,
+                }
+            };
+            let count = self.executed - start;
+            match (state, &entry.event) {
+                (State::Outputting(w), Event::Output(expected))
+                    if w == *expected && count == entry.count => {}
+                (State::Outputting(w), _) => {
+                    return Err(ReplayError::UnexpectedOutput(i, entry.clone(), count, w))
+                }
+                (State::AwaitingInput, Event::Input(w)) if count + 1 == entry.count => {
+                    self.input(w.clone())
+                }
+                (State::AwaitingInput, _) => {
+                    return Err(ReplayError::UnexpectedInput(i, entry.clone(), count + 1))
+                }
+                _ => return Err(ReplayError::UnexpectedEnd(i)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Word> Display for Event<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Input(w) => write!(f, "input {}", w),
+            Event::Output(w) => write!(f, "output {}", w),
+        }
+    }
+}
+
+impl<W: Word> Debug for ReplayError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::UnexpectedOutput(i, entry, count, w) => {
+                write!(
+                    f,
+                    "UnexpectedOutput({}, {:?}, {}, {:?})",
+                    i, entry, count, w
+                )
+            }
+            ReplayError::UnexpectedInput(i, entry, count) => {
+                write!(f, "UnexpectedInput({}, {:?}, {})", i, entry, count)
+            }
+            ReplayError::UnexpectedEnd(i) => write!(f, "UnexpectedEnd({})", i),
+            ReplayError::Exec(e) => write!(f, "Exec({:?})", e),
+        }
+    }
+}
+
+impl<W: Word> Display for ReplayError<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::UnexpectedOutput(i, entry, count, w) => write!(
+                f,
+                "entry {}: expected {} after {} instructions, got output {} after {}",
+                i, entry.event, entry.count, w, count
+            ),
+            ReplayError::UnexpectedInput(i, entry, count) => write!(
+                f,
+                "entry {}: expected {} after {} instructions, got a read after {}",
+                i, entry.event, entry.count, count
+            ),
+            ReplayError::UnexpectedEnd(i) => {
+                write!(f, "entry {}: the program stopped first", i)
+            }
+            ReplayError::Exec(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl<W: Word> error::Error for ReplayError<W> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ReplayError::Exec(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+
+use crate::intcode::trace::Step;
+use crate::intcode::word::Word;
+use crate::intcode::{Insn, Int, Resume, HISTORY, VM};
+
+// A log of how to take back each instruction the VM executes: the cell it
+// wrote and what was there before, where it was and the relative base and
+// extent of memory touched it had, and the input it read. Only the most recent instructions are kept, up to the
+// log's limit.
+
+#[derive(Clone)]
+pub(super) struct UndoLog<W> {
+    entries: VecDeque<Undo<W>>,
+    limit: usize,
+    relbase: Int,
+    touched: usize,
+    // the old value and memory length from a write by the current instruction
+    write: Option<(usize, W, usize)>,
+}
+
+#[derive(Clone)]
+struct Undo<W> {
+    addr: usize,
+    insn: Insn,
+    relbase: Int,
+    touched: usize,
+    write: Option<(usize, W, usize)>,
+    input: Option<W>,
+    output: Option<W>,
+    history: usize,
+}
+
+impl<W: Word> UndoLog<W> {
+    fn new(limit: usize, relbase: Int, touched: usize) -> Self {
+        UndoLog {
+            entries: VecDeque::new(),
+            limit,
+            relbase,
+            touched,
+            write: None,
+        }
+    }
+
+    pub(super) fn write(&mut self, addr: usize, old: W, len: usize) {
+        self.write = Some((addr, old, len));
+    }
+
+    pub(super) fn retire(&mut self, step: &Step<W>, relbase: Int, touched: usize, history: usize) {
+        let entry = Undo {
+            addr: step.addr,
+            insn: step.insn,
+            relbase: self.relbase,
+            touched: self.touched,
+            write: self.write.take(),
+            input: match (step.insn, &step.write) {
+                (Insn::Input, Some((_, w))) => Some(w.clone()),
+                _ => None,
+            },
+            output: match step.insn {
+                Insn::Output => step.args.first().cloned(),
+                _ => None,
+            },
+            history,
+        };
+        self.relbase = relbase;
+        self.touched = touched;
+        if self.limit == 0 {
+            return;
+        }
+        if self.entries.len() == self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+impl<W: Word> VM<W> {
+    // Keeps enough to undo the last `limit` instructions.
+    pub fn with_undo_log(mut self, limit: usize) -> Self {
+        self.set_undo_log(Some(limit));
+        self
+    }
+
+    // Starts a new log, or with `None` stops logging.
+    pub fn set_undo_log(&mut self, limit: Option<usize>) {
+        self.undo = limit.map(|limit| UndoLog::new(limit, self.relbase, self.touched.get()));
+    }
+
+    // How many instructions can be undone.
+    pub fn undo_depth(&self) -> usize {
+        self.undo.as_ref().map_or(0, |undo| undo.entries.len())
+    }
+
+    // Takes back the last instruction, leaving the VM about to execute it
+    // again. Returns false if there's nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.undo.as_mut().and_then(|undo| undo.entries.pop_back()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if let Some((addr, old, len)) = entry.write {
+            self.mem[addr] = old;
+            self.mem.truncate(len);
+        }
+        if let Some(input) = entry.input {
+            self.inbuf.push_front(input);
+        }
+        self.insn = entry.addr;
+        self.relbase = entry.relbase;
+        self.touched.set(entry.touched);
+        let undo = self.undo.as_mut().unwrap();
+        undo.relbase = entry.relbase;
+        undo.touched = entry.touched;
+
+        self.executed -= 1;
+        match &mut self.counts {
+            Some(counts) if self.executed >= counts.from => {
+                counts.history[self.executed % HISTORY] = entry.history;
+                counts.histogram[entry.insn.opcode() as usize] -= 1;
+            }
+            _ => (),
+        }
+        if entry.insn == Insn::End {
+            self.halted = false;
+        } else {
+            self.fuel = self.fuel.map(|fuel| fuel + 1);
+        }
+        // don't stop at a breakpoint straight away when carrying on from here
+        self.resume = Some((entry.addr, Resume::Breakpoint));
+        true
+    }
+
+    // Rewinds to just before the last instruction that wrote to `addr`, and
+    // returns where that instruction is. Nothing is undone if the log doesn't
+    // go back that far.
+    pub fn rewind_to_write(&mut self, addr: usize) -> Option<usize> {
+        self.rewind_to(|entry| matches!(entry.write, Some((a, _, _)) if a == addr))
+    }
+
+    // Rewinds to just before the last output, so running on will produce it
+    // again, and returns the value output.
+    pub fn rewind_to_output(&mut self) -> Option<W> {
+        let undo = self.undo.as_ref()?;
+        let output = undo
+            .entries
+            .iter()
+            .rev()
+            .find_map(|entry| entry.output.clone())?;
+        self.rewind_to(|entry| entry.output.is_some());
+        Some(output)
+    }
+
+    fn rewind_to<F: Fn(&Undo<W>) -> bool>(&mut self, found: F) -> Option<usize> {
+        let undo = self.undo.as_ref()?;
+        let n = undo.entries.iter().rev().position(found)? + 1;
+        for _ in 0..n {
+            self.step_back();
+        }
+        Some(self.insn)
+    }
+}
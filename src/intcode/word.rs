@@ -0,0 +1,48 @@
+use std::fmt::{Debug, Display};
+use std::ops::{BitAnd, BitOr, BitXor, Rem};
+use std::str::FromStr;
+
+use num::{BigInt, CheckedAdd, CheckedDiv, CheckedMul, One, ToPrimitive, Zero};
+
+use crate::intcode::{Error, Int, VMResult};
+
+// The type of a cell of VM memory. Arithmetic on words is checked, so an `Int`
+// VM stops with `Error::Overflow` where a `BigInt` one just keeps growing.
+pub trait Word:
+    Clone
+    + Debug
+    + Display
+    + Ord
+    + From<Int>
+    + FromStr
+    + Zero
+    + One
+    + CheckedAdd
+    + CheckedMul
+    + CheckedDiv
+    + Rem<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + ToPrimitive
+    + Send
+    + Sync
+    + 'static
+{
+    fn to_int(&self) -> VMResult<Int> {
+        self.to_i64().ok_or(Error::Overflow)
+    }
+
+    fn from_ints(ints: &[Int]) -> Vec<Self> {
+        ints.iter().map(|&i| Self::from(i)).collect()
+    }
+}
+
+impl Word for Int {
+    // A straight copy, rather than converting each word in turn.
+    fn from_ints(ints: &[Int]) -> Vec<Self> {
+        ints.to_vec()
+    }
+}
+
+impl Word for BigInt {}
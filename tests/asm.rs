@@ -0,0 +1,111 @@
+use aoc::intcode::asm::AssembleError;
+use aoc::intcode::{Insn, Int, Program, State, VM};
+
+fn outputs(program: &Program) -> Vec<Int> {
+    let mut vm = VM::of(program);
+    let mut outputs = Vec::new();
+    loop {
+        match vm.next_state().unwrap() {
+            State::Outputting(o) => outputs.push(o),
+            State::Finished => return outputs,
+            state => panic!("stopped with {:?}", state),
+        }
+    }
+}
+
+#[test]
+fn labels_and_data() {
+    let program = Program::assemble(
+        "
+                ADD [a], [b], [sum]   ; labels used before they're defined
+                OUTPUT [sum]
+                END
+        a:      .data 20
+        b:      .data 22
+        sum:    .data 0
+        ",
+    )
+    .unwrap();
+    assert_eq!(
+        VM::of(&program).mem.to_vec(),
+        vec![1, 7, 8, 9, 4, 9, 99, 20, 22, 0]
+    );
+    assert_eq!(outputs(&program), vec![42]);
+
+    let program = Program::assemble(
+        "
+        x:      .data 'a', x+1, -x - 2, '\\n'
+                .string \"hi; there\\n\"
+                .data -9223372036854775808
+        ",
+    )
+    .unwrap();
+    let mut expected = vec![97, 1, -2, 10];
+    expected.extend("hi; there\n".bytes().map(Int::from));
+    expected.push(Int::MIN);
+    assert_eq!(VM::of(&program).mem.to_vec(), expected);
+}
+
+#[test]
+fn stack_macros() {
+    let program = Program::assemble(
+        "
+                SET_BASE #stack
+                PUSH #21
+                CALL #double
+                POP [x]
+                OUTPUT [x]
+                END
+        double: MUL rb-2, #2, rb-2    ; the argument is under the return address
+                RET
+        x:      .data 0
+        stack:
+        ",
+    )
+    .unwrap();
+    assert_eq!(outputs(&program), vec![42]);
+}
+
+// Whether an error is the one expected.
+type Check = fn(AssembleError) -> bool;
+
+#[test]
+fn errors() {
+    use AssembleError::*;
+    let cases: &[(&str, Check)] = &[
+        (
+            "FOO #1",
+            |e| matches!(e, UnknownMnemonic(1, ref s) if s == "FOO"),
+        ),
+        (".bss 1", |e| matches!(e, UnknownDirective(1, _))),
+        ("ADD #1, #2", |e| matches!(e, OperandCount(1, Insn::Add, 2))),
+        ("ADD #1, #2, #3", |e| matches!(e, ImmediateWrite(1, _))),
+        ("OUTPUT 5", |e| matches!(e, BadOperand(1, _))),
+        ("PUSH", |e| matches!(e, BadOperand(1, _))),
+        (
+            "x: END\nx: END",
+            |e| matches!(e, DuplicateLabel(2, ref s) if s == "x"),
+        ),
+        (
+            "OUTPUT [y]",
+            |e| matches!(e, UndefinedLabel(1, ref s) if s == "y"),
+        ),
+        (".data 1 +", |e| matches!(e, BadExpression(1, _))),
+        (".data 9223372036854775808", |e| {
+            matches!(e, BadExpression(1, _))
+        }),
+        (".string \"abc", |e| matches!(e, BadString(1, _))),
+        (".data 9223372036854775807+1", |e| matches!(e, Overflow(1))),
+        (".data -9223372036854775807-2", |e| matches!(e, Overflow(1))),
+    ];
+    for (source, expected) in cases {
+        match Program::assemble(source) {
+            Err(e) => assert!(expected(e), "{}", source),
+            Ok(program) => panic!(
+                "{}: assembled to {:?}",
+                source,
+                VM::of(&program).mem.to_vec()
+            ),
+        }
+    }
+}
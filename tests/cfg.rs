@@ -0,0 +1,27 @@
+use aoc::intcode::cfg::Exit;
+use aoc::intcode::Program;
+
+// Calls f, with the jump that makes the call also the target of a loop, so
+// it starts a block of its own after the return address is pushed.
+const LOOP: &str = "
+                SET_BASE #stack
+                ADD #back, #0, rb+0
+        again:  JUMP_IF_TRUE #1, #f
+        back:   JUMP_IF_TRUE [n], #again
+                END
+        f:      JUMP_IF_TRUE #1, rb+0
+        n:      .data 0
+        stack:
+";
+
+#[test]
+fn call_across_blocks() {
+    let cfg = Program::assemble(LOOP).unwrap().cfg();
+    let starts = cfg.blocks().map(|block| block.start).collect::<Vec<_>>();
+    assert_eq!(starts, [0, 6, 9, 12, 13]);
+    assert_eq!(cfg.block(0).unwrap().exit, Exit::Fallthrough(6));
+    assert_eq!(cfg.block(6).unwrap().exit, Exit::Call(Some(13), 9));
+    assert_eq!(cfg.block(9).unwrap().exit, Exit::Branch(6, 12));
+    assert_eq!(cfg.block(13).unwrap().exit, Exit::Return);
+    assert_eq!(cfg.functions().collect::<Vec<_>>(), [0, 13]);
+}
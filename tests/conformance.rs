@@ -0,0 +1,364 @@
+use std::str::FromStr;
+
+use num::BigInt;
+
+use aoc::intcode::word::Word;
+use aoc::intcode::{Error, Insn, Int, Mode, Program, State, VM};
+
+// The example programs from days 2, 5 and 9, and the ways a program can go
+// wrong, run against every way there is of building a VM. A new backend gets
+// the whole suite with a test that calls `conformance` with its constructor.
+
+enum Expect {
+    // finishes, having output these
+    Output(Vec<Int>),
+    // finishes, with these words in memory
+    Memory(Vec<(usize, Int)>),
+    // stops with an error from the instruction at the address
+    Fails(usize, fn(Error) -> bool),
+}
+
+struct Case {
+    name: &'static str,
+    program: &'static str,
+    input: Vec<Int>,
+    expect: Expect,
+}
+
+fn case(name: &'static str, program: &'static str, input: &[Int], expect: Expect) -> Case {
+    Case {
+        name,
+        program,
+        input: input.to_vec(),
+        expect,
+    }
+}
+
+// Day 5's comparison programs, each with what it outputs for an input.
+type Comparison = (&'static str, &'static str, fn(Int) -> Int);
+
+const COMPARISONS: &[Comparison] = &[
+    ("equals, position mode", "3,9,8,9,10,9,4,9,99,-1,8", |i| {
+        (i == 8) as Int
+    }),
+    (
+        "less than, position mode",
+        "3,9,7,9,10,9,4,9,99,-1,8",
+        |i| (i < 8) as Int,
+    ),
+    ("equals, immediate mode", "3,3,1108,-1,8,3,4,3,99", |i| {
+        (i == 8) as Int
+    }),
+    ("less than, immediate mode", "3,3,1107,-1,8,3,4,3,99", |i| {
+        (i < 8) as Int
+    }),
+    (
+        "jump, position mode",
+        "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9",
+        |i| (i != 0) as Int,
+    ),
+    (
+        "jump, immediate mode",
+        "3,3,1105,-1,9,1101,0,0,12,4,12,99,1",
+        |i| (i != 0) as Int,
+    ),
+    (
+        "compare to 8",
+        "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,\
+         1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,\
+         999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99",
+        |i| 1000 + (i - 8).signum(),
+    ),
+];
+
+const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+fn cases() -> Vec<Case> {
+    use Expect::*;
+    let mut cases = vec![
+        // day 2
+        case(
+            "add and multiply",
+            "1,9,10,3,2,3,11,0,99,30,40,50",
+            &[],
+            Memory(vec![(0, 3500), (3, 70)]),
+        ),
+        case("add", "1,0,0,0,99", &[], Memory(vec![(0, 2)])),
+        case("multiply", "2,3,0,3,99", &[], Memory(vec![(3, 6)])),
+        case(
+            "multiply past the end",
+            "2,4,4,5,99,0",
+            &[],
+            Memory(vec![(5, 9801)]),
+        ),
+        case(
+            "overwrite an instruction",
+            "1,1,1,4,99,5,6,0,99",
+            &[],
+            Memory(vec![(0, 30), (4, 2)]),
+        ),
+        // day 5
+        case("echo", "3,0,4,0,99", &[42], Output(vec![42])),
+        case("mixed modes", "1002,4,3,4,33", &[], Memory(vec![(4, 99)])),
+        case(
+            "negative immediate",
+            "1101,100,-1,4,0",
+            &[],
+            Memory(vec![(4, 99)]),
+        ),
+        case("output immediate", "104,-7,99", &[], Output(vec![-7])),
+        // day 9
+        case(
+            "quine",
+            QUINE,
+            &[],
+            Output(QUINE.split(',').map(|w| w.parse().unwrap()).collect()),
+        ),
+        case(
+            "16 digit product",
+            "1102,34915192,34915192,7,4,7,99,0",
+            &[],
+            Output(vec![1_219_070_632_396_864]),
+        ),
+        case(
+            "large number",
+            "104,1125899906842624,99",
+            &[],
+            Output(vec![1_125_899_906_842_624]),
+        ),
+        case(
+            "relative base",
+            "109,2000,109,19,204,-34,99",
+            &[],
+            Output(vec![0]),
+        ),
+        case(
+            "relative input",
+            "109,10,203,-3,204,-3,99",
+            &[5],
+            Output(vec![5]),
+        ),
+        case(
+            "relative write",
+            "109,3,21101,7,8,5,99,0",
+            &[],
+            Memory(vec![(8, 15)]),
+        ),
+        case(
+            "negative relative base",
+            "109,20,109,-15,204,2,99,-3",
+            &[],
+            Output(vec![-3]),
+        ),
+        case(
+            "far memory",
+            "1101,6,7,100000,4,100000,99",
+            &[],
+            Output(vec![13]),
+        ),
+        // errors
+        case(
+            "write in immediate mode",
+            "11101,1,1,0,99",
+            &[],
+            Fails(0, |e| matches!(e, Error::UnsupportedSet(Mode::Immediate))),
+        ),
+        case(
+            "input in immediate mode",
+            "103,0,99",
+            &[1],
+            Fails(0, |e| matches!(e, Error::UnsupportedSet(Mode::Immediate))),
+        ),
+        case(
+            "read a negative address",
+            "4,-1,99",
+            &[],
+            Fails(0, |e| matches!(e, Error::MemoryOutOfBounds(-1))),
+        ),
+        case(
+            "write a negative address",
+            "1101,0,0,-5,99",
+            &[],
+            Fails(0, |e| matches!(e, Error::MemoryOutOfBounds(-5))),
+        ),
+        case(
+            "relative below zero",
+            "109,-2,204,1,99",
+            &[],
+            Fails(2, |e| matches!(e, Error::MemoryOutOfBounds(-1))),
+        ),
+        case(
+            "jump to a negative address",
+            "1105,1,-4,99",
+            &[],
+            Fails(0, |e| matches!(e, Error::MemoryOutOfBounds(-4))),
+        ),
+        case(
+            "jump past the end",
+            "1106,0,50,99",
+            &[],
+            Fails(50, |e| matches!(e, Error::UnterminatedProgram(50))),
+        ),
+        case(
+            "no 99",
+            "1101,1,2,5,104,0",
+            &[],
+            Fails(6, |e| matches!(e, Error::UnterminatedProgram(6))),
+        ),
+        case(
+            "cut short",
+            "1101,1,2",
+            &[],
+            Fails(0, |e| matches!(e, Error::UnterminatedProgram(3))),
+        ),
+        case(
+            "unknown opcode",
+            "1101,1,2,5,42,0,99",
+            &[],
+            Fails(4, |e| matches!(e, Error::UnrecognisedOpcode(42))),
+        ),
+        case(
+            "negative opcode",
+            "-1,99",
+            &[],
+            Fails(0, |e| matches!(e, Error::UnrecognisedOpcode(_))),
+        ),
+        case(
+            "unknown mode",
+            "301,0,0,0,99",
+            &[],
+            Fails(0, |e| matches!(e, Error::UnrecognisedMode(3))),
+        ),
+    ];
+    for &(name, program, expected) in COMPARISONS {
+        for input in &[-1, 0, 7, 8, 9] {
+            cases.push(case(
+                name,
+                program,
+                &[*input],
+                Output(vec![expected(*input)]),
+            ));
+        }
+    }
+    cases
+}
+
+fn conformance<W: Word>(make: impl Fn(&Program) -> VM<W>) {
+    for case in cases() {
+        let what = format!("{} ({:?})", case.name, case.input);
+        let program = Program::from_str(case.program).unwrap();
+        let mut vm = make(&program);
+        case.input.iter().for_each(|&i| vm.input(W::from(i)));
+        let mut outputs = Vec::new();
+        let result = loop {
+            match vm.next_state() {
+                Ok(State::Outputting(o)) => outputs.push(o.to_int().unwrap()),
+                Ok(State::Finished) => break Ok(()),
+                Ok(state) => panic!("{}: stopped with {:?}", what, state),
+                Err(e) => break Err(e),
+            }
+        };
+        match (case.expect, result) {
+            (Expect::Output(expected), Ok(())) => assert_eq!(outputs, expected, "{}", what),
+            (Expect::Memory(expected), Ok(())) => {
+                for (addr, word) in expected {
+                    assert_eq!(
+                        vm.mem[addr].to_int().ok(),
+                        Some(word),
+                        "{}: word {}",
+                        what,
+                        addr
+                    );
+                }
+            }
+            (Expect::Fails(addr, kind), Err(e)) => {
+                assert!(kind(e.error()), "{}: wrong error {:?}", what, e);
+                assert_eq!(e.addr(), addr, "{}: error at {}", what, e.addr());
+            }
+            (_, Ok(())) => panic!("{}: finished, outputting {:?}", what, outputs),
+            (_, Err(e)) => panic!("{}: {:?}", what, e),
+        }
+    }
+
+    // input that isn't there yet waits, and doesn't run anything
+    let mut vm = make(&Program::from_str("3,0,4,0,99").unwrap()).with_counts();
+    assert!(matches!(vm.next_state(), Ok(State::AwaitingInput)));
+    assert!(matches!(vm.next_state(), Ok(State::AwaitingInput)));
+    assert_eq!(vm.executed(), 0);
+    vm.input(W::from(3));
+    match vm.next_state() {
+        Ok(State::Outputting(o)) => assert_eq!(o.to_int().ok(), Some(3)),
+        state => panic!("echo after waiting: {:?}", state),
+    }
+
+    // a finished program stays finished, having run its END once
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+    assert!(vm.is_finished());
+    let executed = vm.executed();
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+    assert_eq!(vm.executed(), executed);
+    assert!(vm.histogram().contains(&(Insn::End, 1)));
+
+    // memory only has a limit when it's given one
+    let far = Program::from_str("1101,5,6,1000,4,1000,99").unwrap();
+    let mut vm = make(&far).with_memory_limit(1000);
+    match vm.next_state() {
+        Err(e) => assert!(matches!(e.error(), Error::MemoryOutOfBounds(1000))),
+        state => panic!("write past the limit: {:?}", state),
+    }
+    let mut vm = make(&far);
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(_))));
+}
+
+#[test]
+fn interpreted() {
+    conformance(VM::of);
+}
+
+#[test]
+fn decode_cache() {
+    conformance(|program| VM::of(program).with_decode_cache());
+}
+
+#[test]
+fn compiled() {
+    conformance(VM::compiled);
+}
+
+#[test]
+fn paged_memory() {
+    conformance(|program| VM::of(program).with_paged_memory());
+}
+
+#[test]
+fn paged_memory_with_decode_cache() {
+    conformance(|program| VM::of(program).with_paged_memory().with_decode_cache());
+}
+
+#[test]
+fn paged_memory_keeps_its_limit() {
+    conformance(|program| {
+        VM::of(program)
+            .with_memory_limit(1 << 24)
+            .with_paged_memory()
+    });
+    let far = Program::from_str("1101,5,6,16777216,99").unwrap();
+    let mut vm = VM::of(&far).with_memory_limit(1 << 24).with_paged_memory();
+    assert!(vm.next_state().is_err());
+}
+
+#[test]
+fn compiled_with_paged_memory() {
+    conformance(|program| VM::of(program).with_paged_memory().with_compiled_code());
+}
+
+#[test]
+fn big_int() {
+    conformance(VM::<BigInt>::new);
+}
+
+#[test]
+fn big_int_compiled() {
+    conformance(|program| VM::<BigInt>::new(program).with_compiled_code());
+}
@@ -0,0 +1,30 @@
+use std::str::FromStr;
+
+use aoc::intcode::ext::Extension;
+use aoc::intcode::{Program, State, VM};
+
+// 7 / 2 by opcode 50, in immediate, immediate and position mode, into [7],
+// then outputs it
+const DIVIDE: &str = "1150,7,2,7,4,7,99,0";
+
+#[test]
+fn run_an_extension() {
+    let program = Program::from_str(DIVIDE).unwrap();
+    for mut vm in [VM::of(&program), VM::compiled(&program)] {
+        vm = vm.with_extension(50, Extension::div());
+        vm.add_breakpoint(0);
+        assert!(matches!(vm.next_state(), Ok(State::Breakpoint(0))));
+        assert!(!vm.is_finished());
+        assert!(matches!(vm.next_state(), Ok(State::Outputting(3))));
+        assert!(vm.is_finished());
+        assert!(matches!(vm.next_state(), Ok(State::Finished)));
+        assert_eq!(vm.executed(), 3);
+    }
+}
+
+#[test]
+fn unregistered_opcodes_are_not_the_end() {
+    let mut vm = VM::of(&Program::from_str(DIVIDE).unwrap());
+    assert!(!vm.is_finished());
+    assert!(vm.next_state().is_err());
+}
@@ -0,0 +1,368 @@
+use std::env;
+use std::fmt::Debug;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use itertools::Itertools;
+
+use aoc::intcode::{ExecError, Int, Program, State, VM};
+
+// Random programs run with random input and a fuel budget, checking that the
+// VM never panics, that its errors describe the state it stopped in, and that
+// a VM cloned partway through, stepped one instruction at a time, or run on
+// another backend gets the same result.
+//
+// `FUZZ_CASES` and `FUZZ_SEED` pick how many programs to try and where to
+// start. A failing program is shrunk and written to tests/regressions, where
+// `regressions` runs it from then on.
+
+const CASES: u64 = 1000;
+const MEMORY_LIMIT: usize = 1 << 16;
+const HISTORY: usize = 16;
+const ERROR_WINDOW: usize = 32;
+const REGRESSIONS: &str = "tests/regressions";
+
+#[derive(Clone, Debug)]
+struct Case {
+    program: Vec<Int>,
+    input: Vec<Int>,
+    fuel: usize,
+    // how many instructions to step through before cloning
+    split: usize,
+}
+
+// How a run ended, for comparing runs.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    outputs: Vec<Int>,
+    end: String,
+    executed: usize,
+    mem: Vec<Int>,
+    ip: usize,
+    relbase: Int,
+}
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        // xorshift64*
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn range(&mut self, lo: Int, hi: Int) -> Int {
+        lo + self.below((hi - lo + 1) as u64) as Int
+    }
+
+    // Mostly instructions and addresses inside the program, with the odd bad
+    // mode and extreme value.
+    fn word(&mut self, len: usize) -> Int {
+        match self.below(10) {
+            0..=4 => {
+                let opcode = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99][self.below(10) as usize];
+                let mut mode = || match self.below(20) {
+                    0 => self.range(3, 9),
+                    _ => self.range(0, 2),
+                };
+                opcode + 100 * mode() + 1000 * mode() + 10000 * mode()
+            }
+            5..=7 => self.range(0, len as Int + 4),
+            8 => self.range(-10, 10),
+            _ => [Int::MIN, Int::MAX, -1, 1 << 40, -(1 << 20)][self.below(5) as usize],
+        }
+    }
+
+    fn case(&mut self) -> Case {
+        let len = self.range(1, 48) as usize;
+        let input_len = self.below(7);
+        Case {
+            program: (0..len).map(|_| self.word(len)).collect(),
+            input: (0..input_len).map(|_| self.range(-5, 50)).collect(),
+            fuel: 1000,
+            split: self.below(200) as usize,
+        }
+    }
+}
+
+fn vm(case: &Case) -> VM {
+    let program = Program::from_str(&case.program.iter().join(",")).unwrap();
+    let mut vm = VM::of(&program)
+        .with_memory_limit(MEMORY_LIMIT)
+        .with_fuel(case.fuel);
+    case.input.iter().for_each(|&i| vm.input(i));
+    vm
+}
+
+// Runs `vm` to a stop, starting from `first` if it has already stopped once.
+fn run(
+    mut vm: VM,
+    mut outputs: Vec<Int>,
+    mut first: Option<Result<State, ExecError>>,
+) -> Result<Outcome, String> {
+    let end = loop {
+        match first.take().unwrap_or_else(|| vm.next_state()) {
+            Ok(State::Outputting(i)) => outputs.push(i),
+            Ok(State::Finished) => {
+                match vm.next_state() {
+                    Ok(State::Finished) => (),
+                    state => return Err(format!("finished, then {:?}", state)),
+                }
+                break "Finished".to_owned();
+            }
+            Ok(state) => break format!("{:?}", state),
+            Err(e) => {
+                check_error(&e, &vm)?;
+                break format!("{:?}", e);
+            }
+        }
+    };
+    Ok(Outcome {
+        outputs,
+        end,
+        executed: vm.executed(),
+        mem: vm.mem.to_vec(),
+        ip: vm.ip(),
+        relbase: vm.relbase(),
+    })
+}
+
+fn check_error(e: &ExecError, vm: &VM) -> Result<(), String> {
+    let (start, window) = e.window();
+    if start > e.addr() || start + window.len() > vm.mem.len() {
+        return Err(format!(
+            "window at {} doesn't fit around {}",
+            start,
+            e.addr()
+        ));
+    }
+    if window.len() > 2 * ERROR_WINDOW + 1 {
+        return Err(format!("window of {} words", window.len()));
+    }
+    if e.history().len() > HISTORY.min(vm.executed()) {
+        return Err(format!("history of {} instructions", e.history().len()));
+    }
+    if e.relbase() != vm.relbase() {
+        return Err(format!(
+            "relative base {} in error, {} in VM",
+            e.relbase(),
+            vm.relbase()
+        ));
+    }
+    if e.to_string().is_empty() {
+        return Err("empty error message".to_owned());
+    }
+    Ok(())
+}
+
+fn same<T: PartialEq + Debug>(what: &str, a: T, b: T) -> Result<(), String> {
+    if a == b {
+        Ok(())
+    } else {
+        Err(format!("{} differs:\n{:?}\n{:?}", what, a, b))
+    }
+}
+
+fn check(case: &Case) -> Result<(), String> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let whole = run(vm(case), Vec::new(), None)?;
+
+        let mut stepped = vm(case);
+        let mut outputs = Vec::new();
+        let mut stop = None;
+        for _ in 0..case.split {
+            match VM::step(&mut stepped) {
+                Ok(None) => (),
+                Ok(Some(State::Outputting(i))) => outputs.push(i),
+                Ok(Some(state)) => {
+                    stop = Some(Ok(state));
+                    break;
+                }
+                Err(e) => {
+                    stop = Some(Err(e));
+                    break;
+                }
+            }
+        }
+        let rest = run(stepped.clone(), outputs.clone(), stop)?;
+        same("stepped run", &whole, &rest)?;
+        same(
+            "cloned run",
+            &run(stepped.clone(), outputs.clone(), None)?,
+            &run(stepped, outputs, None)?,
+        )?;
+
+        same(
+            "compiled run",
+            &whole,
+            &run(vm(case).with_compiled_code(), Vec::new(), None)?,
+        )?;
+        same(
+            "cached run",
+            &whole,
+            &run(vm(case).with_decode_cache(), Vec::new(), None)?,
+        )?;
+        same(
+            "paged run",
+            &whole,
+            &run(vm(case).with_paged_memory(), Vec::new(), None)?,
+        )
+    }));
+    match result {
+        Ok(result) => result,
+        Err(payload) => Err(match payload.downcast_ref::<&str>() {
+            Some(s) => format!("panicked: {}", s),
+            None => match payload.downcast_ref::<String>() {
+                Some(s) => format!("panicked: {}", s),
+                None => "panicked".to_owned(),
+            },
+        }),
+    }
+}
+
+// Cuts the case down while it still fails: dropping words and input, then
+// making what's left smaller.
+fn minimize(mut case: Case) -> Case {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let fails = |case: &Case| check(case).is_err();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..case.program.len()).rev() {
+            let mut smaller = case.clone();
+            smaller.program.remove(i);
+            if fails(&smaller) {
+                case = smaller;
+                changed = true;
+            }
+        }
+        for i in (0..case.input.len()).rev() {
+            let mut smaller = case.clone();
+            smaller.input.remove(i);
+            if fails(&smaller) {
+                case = smaller;
+                changed = true;
+            }
+        }
+        for i in 0..case.program.len() {
+            for &word in &[0, 1, case.program[i] / 2] {
+                if word.unsigned_abs() >= case.program[i].unsigned_abs() {
+                    continue;
+                }
+                let mut smaller = case.clone();
+                smaller.program[i] = word;
+                if fails(&smaller) {
+                    case = smaller;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+        for &split in &[0, case.split / 2] {
+            if split < case.split {
+                let smaller = Case {
+                    split,
+                    ..case.clone()
+                };
+                if fails(&smaller) {
+                    case = smaller;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+    }
+    panic::set_hook(hook);
+    case
+}
+
+fn save(case: &Case, error: &str) -> PathBuf {
+    let program = case.program.iter().join(",");
+    let mut text = String::new();
+    for line in error.lines() {
+        text += &format!("# {}\n", line);
+    }
+    text += &format!("# input: {}\n", case.input.iter().join(","));
+    text += &format!("# fuel: {}\n", case.fuel);
+    text += &format!("# split: {}\n", case.split);
+    text += &program;
+    text += "\n";
+    let hash = program.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100_0000_01b3)
+    });
+    fs::create_dir_all(REGRESSIONS).unwrap();
+    let path = Path::new(REGRESSIONS).join(format!("fuzz-{:016x}.txt", hash));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn load(path: &Path) -> Case {
+    let text = fs::read_to_string(path).unwrap();
+    let header = |name: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(&format!("# {}:", name)))
+            .map(str::trim)
+    };
+    Case {
+        program: VM::of(&Program::from_str(&text).unwrap()).mem.to_vec(),
+        input: header("input")
+            .filter(|input| !input.is_empty())
+            .map(|input| input.split(',').map(|i| i.parse().unwrap()).collect())
+            .unwrap_or_default(),
+        fuel: header("fuel").map_or(1000, |fuel| fuel.parse().unwrap()),
+        split: header("split").map_or(0, |split| split.parse().unwrap()),
+    }
+}
+
+#[test]
+fn fuzz() {
+    let var = |name, default| {
+        env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    let cases = var("FUZZ_CASES", CASES);
+    let seed = var("FUZZ_SEED", 2019);
+    for n in 0..cases {
+        let mut rng = Rng((seed + n).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+        let case = rng.case();
+        if check(&case).is_err() {
+            let case = minimize(case);
+            let error = check(&case).unwrap_err();
+            let path = save(&case, &error);
+            panic!(
+                "case {} of seed {} failed, saved as {}:\n{}",
+                n,
+                seed,
+                path.display(),
+                error
+            );
+        }
+    }
+}
+
+#[test]
+fn regressions() {
+    let paths = match fs::read_dir(REGRESSIONS) {
+        Ok(dir) => dir
+            .map(|entry| entry.unwrap().path())
+            .sorted()
+            .collect_vec(),
+        Err(_) => return,
+    };
+    for path in paths {
+        if let Err(error) = check(&load(&path)) {
+            panic!("{} failed:\n{}", path.display(), error);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::str::FromStr;
+
+use aoc::intcode::network::{Network, Packet, Router};
+use aoc::intcode::{Int, Program, VM};
+
+// Keeps the order packets were sent in, delivering them as addressed.
+#[derive(Default)]
+struct Log(Vec<Int>);
+
+impl Router for Log {
+    fn route(&mut self, packet: Packet) -> Option<Packet> {
+        self.0.push(packet.to);
+        Some(packet)
+    }
+}
+
+// Sends 7 to 1, counts down from 200000, then tells 98 it's done.
+const SENDER: &str = "104,1,104,7,1001,20,-1,20,1005,20,4,104,98,104,0,99,0,0,0,0,200000";
+
+// Tells 99 whatever it's sent, then waits for more.
+const ECHO: &str = "3,10,104,99,4,10,3,10,99,0,0";
+
+// Sends 7 to 1, then waits to be sent something to pass on to 98.
+const ASKER: &str = "104,1,104,7,3,13,104,98,4,13,99,0,0,0";
+
+// Tells 0 whatever it's sent, then waits for more.
+const ANSWER: &str = "3,10,104,0,4,10,3,10,99,0,0";
+
+// The asker can't finish until its answer has been delivered to it, however
+// the threads happen to be scheduled.
+#[test]
+fn threads_deliver_while_the_sender_runs() {
+    let mut network = Network::new(2);
+    network.add(0, VM::of(&Program::from_str(ASKER).unwrap()));
+    network.add(1, VM::of(&Program::from_str(ANSWER).unwrap()));
+    let mut log = Log::default();
+    network.run_threaded(&mut log).unwrap();
+    assert_eq!(log.0, vec![1, 0, 98]);
+    assert!(network.vm(0).unwrap().is_finished());
+}
+
+#[test]
+fn one_thread_runs_each_vm_in_turn() {
+    let mut network = Network::new(2);
+    network.add(0, VM::of(&Program::from_str(SENDER).unwrap()));
+    network.add(1, VM::of(&Program::from_str(ECHO).unwrap()));
+    let mut log = Log::default();
+    network.run(&mut log).unwrap();
+    assert_eq!(log.0, vec![1, 98, 99]);
+}
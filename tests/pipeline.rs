@@ -0,0 +1,42 @@
+use std::str::FromStr;
+
+use aoc::intcode::pipeline::{Pipeline, PipelineError};
+use aoc::intcode::{Int, Program, VM};
+
+// day 7's second feedback loop example, which peaks at 18216
+const AMPLIFIER: &str = "3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,\
+                         -5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,\
+                         53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10";
+
+fn amplifiers(n: usize) -> Vec<VM> {
+    let program = Program::from_str(AMPLIFIER).unwrap();
+    (0..n).map(|_| VM::of(&program)).collect()
+}
+
+#[test]
+fn feedback_loop() {
+    let mut pipeline = Pipeline::ring(amplifiers(5)).with_phases(vec![9, 7, 8, 5, 6]);
+    pipeline.input(0, 0).unwrap();
+    assert_eq!(pipeline.run().unwrap(), 18216);
+}
+
+#[test]
+fn missing_stages() {
+    assert!(matches!(
+        Pipeline::<Int>::ring(Vec::new()).run(),
+        Err(PipelineError::NoOutput)
+    ));
+    assert!(matches!(
+        Pipeline::series(amplifiers(2)).input(2, 0),
+        Err(PipelineError::NoStage(2))
+    ));
+    assert!(Pipeline::series(amplifiers(2)).stage(2).is_none());
+    assert!(matches!(
+        Pipeline::series(amplifiers(2)).with_link(1, 5).run(),
+        Err(PipelineError::NoStage(5))
+    ));
+    assert!(matches!(
+        Pipeline::series(amplifiers(2)).with_output(3).run(),
+        Err(PipelineError::NoStage(3))
+    ));
+}
@@ -0,0 +1,72 @@
+use aoc::intcode::profile::Profile;
+use aoc::intcode::{Program, State, VM};
+
+// Counts n down from 3 in a loop, then calls a function to output it, and
+// never takes the branch after.
+const COUNTDOWN: &str = "
+                SET_BASE #stack
+        loop:   ADD [n], #-1, [n]
+                JUMP_IF_TRUE [n], #loop
+                CALL #f
+                JUMP_IF_TRUE [n], #dead
+                END
+        dead:   OUTPUT #1
+                END
+        f:      OUTPUT [n]
+                RET
+        n:      .data 3
+        stack:
+";
+
+fn profile(program: &Program) -> Profile {
+    let mut vm = VM::of(program);
+    let profile = vm.profile();
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(0))));
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+    let profile = profile.lock().unwrap().clone();
+    profile
+}
+
+#[test]
+fn hot_loops() {
+    let profile = profile(&Program::assemble(COUNTDOWN).unwrap());
+    let loops = profile.hot_loops();
+    assert_eq!(loops.len(), 1);
+    // the ADD at 2 and the JUMP_IF_TRUE at 6 back to it, both run three times
+    assert_eq!((loops[0].head, loops[0].tail), (2, 6));
+    assert_eq!(loops[0].iterations, 2);
+    assert_eq!(loops[0].executed, 6);
+}
+
+#[test]
+fn unexecuted() {
+    let program = Program::assemble(COUNTDOWN).unwrap();
+    let profile = profile(&program);
+    let unexecuted = profile.unexecuted();
+    assert_eq!(unexecuted.len(), 1);
+    // OUTPUT #1 and END
+    let mem = VM::of(&program).mem.to_vec();
+    assert_eq!(mem[unexecuted[0].clone()], [104, 1, 99]);
+}
+
+#[test]
+fn write_collapsed() {
+    let program = Program::assemble(COUNTDOWN).unwrap();
+    let profile = profile(&program);
+    let mut out = Vec::new();
+    profile.write_collapsed(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{}", out);
+
+    let (stack, count) = lines[1].split_once(' ').unwrap();
+    let f = stack
+        .strip_prefix("main;fn@")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    assert_eq!(VM::of(&program).mem[f], 4, "{} isn't the OUTPUT in f", f);
+    let main = lines[0].strip_prefix("main ").unwrap();
+    let total = main.parse::<usize>().unwrap() + count.parse::<usize>().unwrap();
+    assert_eq!(total, profile.executed());
+}
@@ -0,0 +1,72 @@
+use std::str::FromStr;
+
+use aoc::intcode::snapshot::SnapshotError;
+use aoc::intcode::{Int, Program, State, VM};
+
+// writes 13 far out and outputs it
+const FAR: &str = "1101,6,7,16777216,4,16777216,99";
+
+fn reload(vm: &VM) -> Result<VM, SnapshotError> {
+    let mut saved = Vec::new();
+    vm.save(&mut saved).unwrap();
+    VM::load(&saved[..])
+}
+
+#[test]
+fn paged_memory_keeps_the_program_out_of_the_pages() {
+    let mut vm = VM::of(&Program::from_str(FAR).unwrap()).with_paged_memory();
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(13))));
+    let mut vm = reload(&vm).unwrap();
+    assert!(vm.mem.is_paged());
+    assert_eq!(vm.mem.words().len(), 7);
+    assert_eq!(vm.mem[16777216], 13);
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+}
+
+#[test]
+fn dense_memory_loads_up_to_its_limit() {
+    let mut vm = VM::of(&Program::from_str(FAR).unwrap()).with_memory_limit(1 << 30);
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(13))));
+    let vm = reload(&vm).unwrap();
+    assert_eq!(vm.mem.len(), 16777217);
+    assert_eq!(vm.mem.limit(), Some(1 << 30));
+}
+
+#[test]
+fn counts_and_fuel_are_kept() {
+    let mut vm = VM::of(&Program::from_str("104,1,99").unwrap()).with_fuel(5);
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(1))));
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+    let mut vm = reload(&vm).unwrap();
+    assert_eq!(vm.executed(), 2);
+    assert_eq!(vm.fuel(), Some(4));
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+    assert_eq!(vm.executed(), 2);
+}
+
+#[test]
+fn memory_that_wont_fit_is_refused() {
+    for bad in &[
+        "limit 100\nlen 2305843009213693951",
+        "limit 100\nmem 99 1,2",
+        "mem 18446744073709551615 1,2",
+        "len 20000000",
+        "limit 18446744073709551615\nlen 2305843009213693951",
+        "paged 2305843009213693951",
+    ] {
+        let snapshot = format!("intcode-snapshot 1\nip 0\n{}\n", bad);
+        assert!(
+            matches!(
+                VM::<Int>::load(snapshot.as_bytes()),
+                Err(SnapshotError::BadValue(_, _))
+            ),
+            "{}",
+            bad
+        );
+    }
+    let snapshot = "intcode-snapshot 1\nip 0\npaged\nlen 20000000\nmem 19999999 5\n";
+    assert_eq!(
+        VM::<Int>::load(snapshot.as_bytes()).unwrap().mem[19999999],
+        5
+    );
+}
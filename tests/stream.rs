@@ -0,0 +1,65 @@
+use std::str::FromStr;
+
+use futures::executor::block_on;
+use futures::{future, poll, SinkExt, StreamExt};
+
+use aoc::intcode::{Program, VM};
+
+// outputs 1, 2 and 3
+const COUNT: &str = "104,1,104,2,104,3,99";
+// reads three numbers, outputting each doubled
+const DOUBLE: &str = "3,17,102,2,17,17,4,17,1001,16,-1,16,1005,16,0,99,3,0";
+
+fn vm(program: &str) -> VM {
+    VM::of(&Program::from_str(program).unwrap())
+}
+
+#[test]
+fn forward_one_vm_into_another() {
+    let (sink, stream) = vm(DOUBLE).into_stream().split();
+    let source = vm(COUNT).into_stream();
+    let (sent, outputs) = block_on(future::join(
+        source.forward(sink),
+        stream.map(Result::unwrap).collect::<Vec<_>>(),
+    ));
+    assert!(sent.is_ok());
+    assert_eq!(outputs, vec![2, 4, 6]);
+}
+
+#[test]
+fn wait_for_fuel() {
+    let mut stream = vm(COUNT).with_fuel(1).into_stream();
+    block_on(async {
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        assert!(poll!(stream.next()).is_pending());
+        assert!(poll!(stream.next()).is_pending());
+        stream.set_fuel(None);
+        let rest = stream
+            .by_ref()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rest, vec![2, 3]);
+        assert!(stream.next().await.is_none());
+    });
+}
+
+#[test]
+fn send_input_while_waiting() {
+    let mut stream = vm(DOUBLE).into_stream();
+    block_on(async {
+        assert!(poll!(stream.next()).is_pending());
+        stream.send(21).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), 42);
+        stream
+            .send_all(&mut futures::stream::iter(vec![Ok(1), Ok(2)]))
+            .await
+            .unwrap();
+        let rest = stream
+            .by_ref()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rest, vec![2, 4]);
+    });
+}
@@ -0,0 +1,81 @@
+use aoc::intcode::symbolic::{satisfy, Constraint, Linear, Rel, Symbolic};
+use aoc::intcode::{Int, Program, VM};
+
+// `k0*x0 + k1*x1 + ... + constant rel 0`
+fn constraint(terms: &[(usize, Int)], constant: Int, rel: Rel) -> Constraint {
+    let expr = terms.iter().fold(Linear::of(constant), |sum, &(var, k)| {
+        sum.add(&Linear::var(var).scale(k).unwrap()).unwrap()
+    });
+    Constraint { expr, rel }
+}
+
+#[test]
+fn tighten_to_the_only_solution() {
+    // x0 + x1 == 10 and x0 - x1 == 4
+    let system = [
+        constraint(&[(0, 1), (1, 1)], -10, Rel::Eq),
+        constraint(&[(0, 1), (1, -1)], -4, Rel::Eq),
+    ];
+    assert_eq!(
+        satisfy(&system, &[0..=100, 0..=100], &[0, 0]),
+        Some(vec![7, 3])
+    );
+    // 2*x0 >= 7 and x0 < 5
+    let system = [
+        constraint(&[(0, 2)], -7, Rel::Ge),
+        constraint(&[(0, 1)], -5, Rel::Lt),
+    ];
+    assert_eq!(satisfy(&system, &[-100..=100], &[0]), Some(vec![4]));
+}
+
+#[test]
+fn exclude_the_ends_of_the_bounds() {
+    // x0 != 0 and x0 != 1
+    let system = [
+        constraint(&[(0, 1)], 0, Rel::Ne),
+        constraint(&[(0, 1)], -1, Rel::Ne),
+    ];
+    assert_eq!(satisfy(&system, &[0..=1], &[0]), None);
+    assert_eq!(satisfy(&system, &[0..=2], &[0]), Some(vec![2]));
+}
+
+#[test]
+fn split_when_tightening_stops() {
+    // 3*x0 + 5*x1 == 31, which bounds alone don't pin down
+    let system = [constraint(&[(0, 3), (1, 5)], -31, Rel::Eq)];
+    let values = satisfy(&system, &[0..=10, 0..=10], &[0, 0]).unwrap();
+    assert!(system[0].holds(&values), "{:?}", values);
+    assert!(values.iter().all(|v| (0..=10).contains(v)));
+}
+
+#[test]
+fn impossible_systems() {
+    let bounds = [0..=4, 0..=4];
+    // x0 + x1 == 10, out of reach
+    let sum = constraint(&[(0, 1), (1, 1)], -10, Rel::Eq);
+    assert_eq!(satisfy(&[sum], &bounds, &[0, 0]), None);
+    // x0 < 2 and x0 >= 2
+    let system = [
+        constraint(&[(0, 1)], -2, Rel::Lt),
+        constraint(&[(0, 1)], -2, Rel::Ge),
+    ];
+    assert_eq!(satisfy(&system, &bounds, &[0, 0]), None);
+    // 2*x0 == 3 has no whole solution
+    let odd = constraint(&[(0, 2)], -3, Rel::Eq);
+    assert_eq!(satisfy(&[odd], &bounds, &[0, 0]), None);
+}
+
+#[test]
+fn solve_day_2() {
+    let program = Program::from_file("input/2.txt").unwrap();
+    let values = Symbolic::new(&program)
+        .with_cell(1, 0..=99)
+        .with_cell(2, 0..=99)
+        .solve(&[12, 2], |run| vec![run.cell(0).equals(19690720)])
+        .expect("the solver gave up");
+    let mut vm = VM::of(&program);
+    vm.mem[1] = values[0];
+    vm.mem[2] = values[1];
+    vm.next_state().unwrap();
+    assert_eq!(vm.mem[0], 19690720);
+}
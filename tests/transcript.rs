@@ -0,0 +1,49 @@
+use std::str::FromStr;
+
+use aoc::intcode::transcript::{ReplayError, Transcript};
+use aoc::intcode::{Program, State, VM};
+
+// reads a number and outputs it plus the word at 10
+const ADD: &str = "3,9,1,9,10,9,4,9,99,0,1";
+
+fn program() -> Program {
+    Program::from_str(ADD).unwrap()
+}
+
+// Changes the addend to 5, then records adding it to 2.
+fn record(from_program: bool) -> Transcript {
+    let mut vm = VM::of(&program());
+    vm.mem[10] = 5;
+    let recording = match from_program {
+        true => vm.record_from(&program()),
+        false => vm.record(),
+    };
+    vm.input(2);
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(7))));
+    assert!(matches!(vm.next_state(), Ok(State::Finished)));
+    let transcript = recording.lock().unwrap().clone();
+    transcript
+}
+
+#[test]
+fn record_changes_to_memory() {
+    let transcript = record(true);
+    assert_eq!(transcript.patches(), &[(10, 5)]);
+
+    let mut saved = Vec::new();
+    transcript.save(&mut saved).unwrap();
+    let loaded = Transcript::load(&saved[..]).unwrap();
+    assert_eq!(loaded.patches(), transcript.patches());
+    assert_eq!(loaded.entries(), transcript.entries());
+    assert!(VM::of(&program()).replay(&loaded).is_ok());
+}
+
+#[test]
+fn replay_without_the_changes() {
+    let transcript = record(false);
+    assert!(transcript.patches().is_empty());
+    assert!(matches!(
+        VM::of(&program()).replay(&transcript),
+        Err(ReplayError::UnexpectedOutput(1, _, _, 3))
+    ));
+}
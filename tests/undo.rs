@@ -0,0 +1,28 @@
+use std::str::FromStr;
+
+use aoc::intcode::{Program, State, VM};
+
+// reads a number into 10000 and outputs what's at 20000
+const FAR: &str = "3,10000,4,20000,99";
+
+#[test]
+fn step_back_restores_memory_touched() {
+    let program = Program::from_str(FAR).unwrap();
+    let mut vm = VM::of(&program).with_paged_memory().with_undo_log(10);
+    let touched = vm.memory_touched();
+    vm.input(7);
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(0))));
+    assert_eq!((vm.mem.len(), vm.memory_touched()), (10001, 20001));
+
+    assert!(vm.step_back());
+    assert_eq!((vm.mem.len(), vm.memory_touched()), (10001, 10001));
+    assert!(vm.step_back());
+    assert_eq!((vm.mem.len(), vm.memory_touched()), (5, touched));
+    assert_eq!(vm.mem[10000], 0);
+    assert!(!vm.step_back());
+
+    // and running on again reads the same input
+    assert!(matches!(vm.next_state(), Ok(State::Outputting(0))));
+    assert_eq!(vm.mem[10000], 7);
+    assert_eq!((vm.mem.len(), vm.memory_touched()), (10001, 20001));
+}